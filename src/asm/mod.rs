@@ -0,0 +1,91 @@
+/// disassemble selected function from artifact of each (toolchain, profile) and diff it
+/// against baseline toolchain
+use crate::config::{Config, CrateOpt, Profile, ToolchainConfig};
+
+/// disassemble `symbol` from the unstripped artifact and save it into the target folder,
+/// returns the saved asm file
+pub fn asm_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    symbol: &str,
+    config: &Config,
+) -> anyhow::Result<std::path::PathBuf> {
+    let target_folder = crate::utils::target_folder(krate, profile, toolchain, config);
+    let unstripped = crate::utils::cargo_output_path(krate, profile, toolchain, config);
+    if !unstripped.exists() {
+        anyhow::bail!("artifact {unstripped:?} not found, run build-crate first");
+    }
+
+    let lines = crate::binary::disassemble(&unstripped, symbol)?;
+    if lines.is_empty() {
+        log::warn!(
+            "{symbol} not found in {}_{}, it may be inlined",
+            toolchain.name,
+            profile.name
+        );
+    }
+
+    let file_name = symbol
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let asm_folder = target_folder.join("asm");
+    std::fs::create_dir_all(&asm_folder)?;
+    let asm_file = asm_folder.join(format!("{file_name}.s"));
+    std::fs::write(&asm_file, lines.join("\n"))?;
+
+    Ok(asm_file)
+}
+
+/// diff asm of `symbol` for each toolchain and profile against the same profile of baseline
+/// toolchain, returns the concatenated diff output
+pub fn asm_diff_for_all_profile(
+    krate: &CrateOpt,
+    symbol: &str,
+    baseline: &str,
+    side_by_side: bool,
+    config: &Config,
+) -> anyhow::Result<String> {
+    let baseline_toolchain = config.toolchain(baseline)?;
+    let mut result = String::new();
+
+    for toolchain in config.toolchains.iter().filter(|t| t.name.ne(baseline)) {
+        for profile in toolchain.profiles.iter() {
+            if !baseline_toolchain.profiles.contains(profile) {
+                log::warn!("baseline {baseline} has no profile {profile}, skip");
+                continue;
+            }
+            let profile = config.profile(profile)?;
+
+            let base_file = asm_step(krate, baseline_toolchain, profile, symbol, config)?;
+            let asm_file = asm_step(krate, toolchain, profile, symbol, config)?;
+
+            let base_label = format!("{}:{}", baseline, profile.name);
+            let label = format!("{}:{}", toolchain.name, profile.name);
+            let mut cmd = std::process::Command::new("diff");
+            if side_by_side {
+                cmd.args(["-y", "-W", "200"]);
+                result.push_str(&format!("=== {base_label} | {label}\n"));
+            } else {
+                cmd.args(["-u", "--label", &base_label, "--label", &label]);
+            }
+            let output = cmd.arg(&base_file).arg(&asm_file).output()?;
+
+            // diff exits with 1 when files differ
+            if output.status.code().map(|c| c > 1).unwrap_or(true) {
+                anyhow::bail!(
+                    "failed to diff {base_file:?} {asm_file:?}: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+            if output.stdout.is_empty() {
+                result.push_str(&format!("{base_label} and {label} are identical\n"));
+            } else {
+                result.push_str(&String::from_utf8_lossy(&output.stdout));
+            }
+        }
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,169 @@
+/// benchmark environment stabilization: pin the measured child to cpus, lower or raise its
+/// cpu and io priority, and check machine conditions before running
+use crate::config::BenchOpt;
+use serde::{Deserialize, Serialize};
+use std::os::unix::process::CommandExt;
+
+/// machine conditions when the samples were taken, recorded in report so noisy runs can
+/// be recognized later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchConditions {
+    /// 1 minute load average before sampling
+    pub load_avg_1m: f64,
+
+    /// distinct cpu frequency governors of the cpus in use, e.g: `performance`
+    pub cpu_governor: Option<String>,
+
+    /// cpus the measured child is pinned to
+    pub cpu_affinity: Option<String>,
+
+    /// nice value of the measured child
+    pub nice: Option<i32>,
+
+    /// io priority of the measured child, as `class:level`
+    pub ionice: Option<String>,
+}
+
+/// collect current conditions, warn if they are likely to make results noisy
+pub fn check_conditions(opt: &BenchOpt) -> anyhow::Result<BenchConditions> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg").unwrap_or_default();
+    let load_avg_1m = loadavg
+        .split_whitespace()
+        .next()
+        .and_then(|l| l.parse::<f64>().ok())
+        .unwrap_or_default();
+    if load_avg_1m > opt.max_load {
+        log::warn!(
+            "load average {load_avg_1m} is higher than {}, results may be noisy",
+            opt.max_load
+        );
+    }
+
+    let cpus = match opt.cpus.as_ref() {
+        Some(cpus) => parse_cpu_list(cpus)?,
+        None => (0..num_cpus()).collect(),
+    };
+    let mut governors = cpus
+        .iter()
+        .filter_map(|cpu| {
+            std::fs::read_to_string(format!(
+                "/sys/devices/system/cpu/cpu{cpu}/cpufreq/scaling_governor"
+            ))
+            .ok()
+        })
+        .map(|g| g.trim().to_string())
+        .collect::<Vec<_>>();
+    governors.sort();
+    governors.dedup();
+    if governors.iter().any(|g| g != "performance") {
+        log::warn!(
+            "cpu frequency governor is {governors:?}, not performance, results may be noisy"
+        );
+    }
+
+    Ok(BenchConditions {
+        load_avg_1m,
+        cpu_governor: (!governors.is_empty()).then(|| governors.join(",")),
+        cpu_affinity: opt.cpus.clone(),
+        nice: opt.nice,
+        ionice: opt
+            .ionice_class
+            .map(|class| format!("{}:{}", class, opt.ionice_level.unwrap_or(0))),
+    })
+}
+
+/// apply cpu affinity and priorities to the measured child before it execs
+pub fn apply(cmd: &mut std::process::Command, opt: &BenchOpt) -> anyhow::Result<()> {
+    let cpus = opt.cpus.as_ref().map(|c| parse_cpu_list(c)).transpose()?;
+    let nice = opt.nice;
+    let ioprio = opt
+        .ionice_class
+        .map(|class| (class << 13) | opt.ionice_level.unwrap_or(0));
+
+    if cpus.is_none() && nice.is_none() && ioprio.is_none() {
+        return Ok(());
+    }
+
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(cpus) = cpus.as_ref() {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                for cpu in cpus.iter() {
+                    libc::CPU_SET(*cpu, &mut set);
+                }
+                if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(ioprio) = ioprio {
+                // IOPRIO_WHO_PROCESS = 1
+                if libc::syscall(libc::SYS_ioprio_set, 1, 0, ioprio) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+/// human readable settings `apply` applies, for `--dry-run`
+pub fn describe(opt: &BenchOpt) -> Vec<String> {
+    let mut settings = vec![];
+    if let Some(cpus) = opt.cpus.as_ref() {
+        settings.push(format!("pinned to cpus {cpus}"));
+    }
+    if let Some(nice) = opt.nice {
+        settings.push(format!("nice {nice}"));
+    }
+    if let Some(class) = opt.ionice_class {
+        settings.push(format!(
+            "ionice class {class} level {}",
+            opt.ionice_level.unwrap_or(0)
+        ));
+    }
+    settings
+}
+
+fn num_cpus() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// parse cpu list in `taskset -c` format, e.g: `0,2-3`. Every cpu must be online and
+/// allowed for ctm, so pinning the child can't fail after fork
+fn parse_cpu_list(cpus: &str) -> anyhow::Result<Vec<usize>> {
+    let mut result = vec![];
+    for part in cpus.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => result.extend(start.parse::<usize>()?..=end.parse::<usize>()?),
+            None => result.push(part.parse::<usize>()?),
+        }
+    }
+    if result.is_empty() {
+        anyhow::bail!("empty cpu list {cpus:?}");
+    }
+
+    // offline cpus are never in the affinity mask
+    let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut allowed) }
+        != 0
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let setsize = 8 * std::mem::size_of::<libc::cpu_set_t>();
+    if let Some(cpu) = result
+        .iter()
+        .find(|cpu| **cpu >= setsize || !unsafe { libc::CPU_ISSET(**cpu, &allowed) })
+    {
+        anyhow::bail!("cpu {cpu} of cpu list {cpus:?} is offline or not allowed");
+    }
+    Ok(result)
+}
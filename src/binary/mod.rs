@@ -0,0 +1,305 @@
+/// helpers to inspect ELF artifacts, backed by binutils (`readelf`, `nm`)
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// section name, e.g: `.text`
+    pub name: String,
+
+    /// offset in file
+    pub offset: u64,
+
+    /// section size in bytes
+    pub size: u64,
+
+    /// whether the section occupies space in file, `.bss` doesn't
+    pub in_file: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    /// demangled symbol name
+    pub name: String,
+
+    /// symbol size in bytes
+    pub size: u64,
+}
+
+/// list sections of the ELF file with `readelf -S -W`
+pub fn sections(path: &std::path::Path) -> anyhow::Result<Vec<Section>> {
+    let output = cmd_lib::run_fun!(readelf -S -W $path)?;
+    Ok(parse_sections(&output))
+}
+
+/// sections from the output of `readelf -S -W`, without the null section
+fn parse_sections(output: &str) -> Vec<Section> {
+    let mut sections = vec![];
+    for line in output.lines() {
+        // [ 1] .interp  PROGBITS  00000000000002e0 0002e0 00001c 00   A  0   0  1
+        let line = match line.trim().strip_prefix('[') {
+            Some(line) => line,
+            None => continue,
+        };
+        let (nr, rest) = match line.split_once(']') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let fields = rest.split_whitespace().collect::<Vec<_>>();
+        if nr.trim() == "0" || fields.len() < 5 {
+            continue;
+        }
+        let (offset, size) = match (
+            u64::from_str_radix(fields[3], 16),
+            u64::from_str_radix(fields[4], 16),
+        ) {
+            (Ok(offset), Ok(size)) => (offset, size),
+            _ => continue,
+        };
+
+        sections.push(Section {
+            name: fields[0].to_string(),
+            offset,
+            size,
+            in_file: fields[1] != "NOBITS",
+        });
+    }
+
+    sections
+}
+
+/// find the first section (by offset) whose content differs between two ELF files.
+/// `None` if all sections are identical, then only headers or padding differ
+pub fn first_diff_section(
+    a: &std::path::Path,
+    b: &std::path::Path,
+) -> anyhow::Result<Option<String>> {
+    let a_content = std::fs::read(a)?;
+    let b_content = std::fs::read(b)?;
+
+    let mut a_sections = sections(a)?;
+    a_sections.sort_by_key(|s| s.offset);
+    let b_sections = sections(b)?;
+
+    for section in a_sections.iter().filter(|s| s.in_file) {
+        let other = match b_sections.iter().find(|s| s.name == section.name) {
+            Some(other) => other,
+            None => return Ok(Some(section.name.clone())),
+        };
+        let a_range = section.offset as usize..(section.offset + section.size) as usize;
+        let b_range = other.offset as usize..(other.offset + other.size) as usize;
+        if a_content.get(a_range) != b_content.get(b_range) {
+            return Ok(Some(section.name.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// list sized symbols of the ELF file with `nm -S -C`, largest first
+pub fn symbols(path: &std::path::Path) -> anyhow::Result<Vec<Symbol>> {
+    let output = cmd_lib::run_fun!(nm -S -C --size-sort $path)?;
+    Ok(parse_symbols(&output))
+}
+
+/// sized symbols from the output of `nm -S -C --size-sort`, largest first
+fn parse_symbols(output: &str) -> Vec<Symbol> {
+    let mut symbols = vec![];
+    for line in output.lines() {
+        // 00000000004cc1c0 000000000000c415 t core::fmt::write
+        let mut fields = line.splitn(4, ' ');
+        let (_addr, size, _ty, name) =
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(addr), Some(size), Some(ty), Some(name)) => (addr, size, ty, name),
+                _ => continue,
+            };
+        let size = match u64::from_str_radix(size, 16) {
+            Ok(size) => size,
+            Err(_) => continue,
+        };
+
+        symbols.push(Symbol {
+            name: name.to_string(),
+            size,
+        });
+    }
+
+    symbols.reverse();
+    symbols
+}
+
+/// guess which crate the demangled symbol comes from by its path prefix,
+/// `<alloc::vec::Vec<T> as core::ops::Drop>::drop` is attributed to `alloc`,
+/// `<u64 as core::fmt::Debug>::fmt` is attributed to `core`
+pub fn symbol_crate(name: &str) -> String {
+    let name = name
+        .trim_start_matches(['<', '&', '*', '(', '['])
+        .trim_start_matches("mut ")
+        .trim_start_matches("const ")
+        .trim_start_matches("dyn ");
+    let ident_end = name
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(name.len());
+
+    if ident_end > 0 && name[ident_end..].starts_with("::") {
+        name[..ident_end].to_string()
+    } else if let Some((_, trait_path)) = name.split_once(" as ") {
+        symbol_crate(trait_path)
+    } else {
+        "[unknown]".to_string()
+    }
+}
+
+/// disassemble functions whose demangled name matches `symbol` with `objdump -d`, exact
+/// matches are preferred over partial ones. Addresses are dropped from the output, so
+/// the same code placed at different address looks identical
+pub fn disassemble(path: &std::path::Path, symbol: &str) -> anyhow::Result<Vec<String>> {
+    let output = cmd_lib::run_fun!(objdump -d -C --no-show-raw-insn $path)?;
+
+    // (function name, instructions)
+    let mut functions: Vec<(String, Vec<String>)> = vec![];
+    let mut current: Option<(String, Vec<String>)> = None;
+    for line in output.lines() {
+        // 00000000000141a0 <democrate::fib>:
+        if let Some(name) = line
+            .split_once(" <")
+            .filter(|(addr, _)| !addr.starts_with(' ') && !addr.is_empty())
+            .and_then(|(_, rest)| rest.strip_suffix(">:"))
+        {
+            functions.extend(current.take());
+            current = Some((name.to_string(), vec![]));
+            continue;
+        }
+
+        // 141b4:	call   141a0 <democrate::fib>
+        match (current.as_mut(), line.split_once(":\t")) {
+            (Some((_, instructions)), Some((_addr, instruction))) => {
+                instructions.push(strip_addresses(instruction));
+            }
+            _ => {
+                if line.trim().is_empty() {
+                    functions.extend(current.take());
+                }
+            }
+        }
+    }
+    functions.extend(current.take());
+
+    let exact = functions.iter().any(|(name, _)| name == symbol);
+    let mut lines = vec![];
+    for (name, instructions) in functions.into_iter() {
+        let matched = if exact {
+            name == symbol
+        } else {
+            name.contains(symbol)
+        };
+        if matched {
+            lines.push(format!("<{name}>:"));
+            lines.extend(instructions);
+            lines.push(String::new());
+        }
+    }
+
+    Ok(lines)
+}
+
+/// drop hex addresses followed by a symbol, `call   141a0 <democrate::fib>` becomes
+/// `call   <democrate::fib>`, and `mov 0x1d79a(%rip),%rax # <COUNTER>` becomes
+/// `mov (%rip),%rax # <COUNTER>`
+fn strip_addresses(instruction: &str) -> String {
+    let instruction = match instruction.split_once("(%rip)") {
+        Some((before, after)) if after.contains('#') => {
+            let displacement_start = before
+                .rfind(|c: char| !(c.is_ascii_hexdigit() || c == 'x' || c == '-'))
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            format!("{}(%rip){}", &before[..displacement_start], after)
+        }
+        _ => instruction.to_string(),
+    };
+
+    let words = instruction.split(' ').collect::<Vec<_>>();
+    let mut result = vec![];
+    for (i, word) in words.iter().enumerate() {
+        let is_address = !word.is_empty()
+            && word.chars().all(|c| c.is_ascii_hexdigit())
+            && words
+                .get(i + 1)
+                .map(|w| w.starts_with('<'))
+                .unwrap_or(false);
+        if !is_address {
+            result.push(*word);
+        }
+    }
+    result.join(" ").trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readelf_sections() {
+        let output = "
+There are 4 section headers, starting at offset 0x1c0e8:
+
+Section Headers:
+  [Nr] Name              Type            Address          Off    Size   ES Flg Lk Inf Al
+  [ 0]                   NULL            0000000000000000 000000 000000 00      0   0  0
+  [ 1] .interp           PROGBITS        00000000000002e0 0002e0 00001c 00   A  0   0  1
+  [ 2] .text             PROGBITS        0000000000007000 007000 0141a3 00  AX  0   0 16
+  [13] .bss              NOBITS          000000000001d1a8 01c1a8 000008 00  WA  0   0  8
+Key to Flags:
+  W (write), A (alloc), X (execute), M (merge), S (strings), I (info),
+";
+        let sections = parse_sections(output)
+            .into_iter()
+            .map(|s| (s.name, s.offset, s.size, s.in_file))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            vec![
+                (".interp".to_string(), 0x2e0, 0x1c, true),
+                (".text".to_string(), 0x7000, 0x141a3, true),
+                (".bss".to_string(), 0x1c1a8, 0x8, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn nm_symbols_largest_first() {
+        let output = "\
+000000000001d1a8 0000000000000008 b std::sys::COUNTER
+0000000000007010 0000000000000010 t democrate::fib
+0000000000007100 0000000000000a2c T <alloc::vec::Vec<T> as core::ops::drop::Drop>::drop
+";
+        let symbols = parse_symbols(output)
+            .into_iter()
+            .map(|s| (s.name, s.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![
+                (
+                    "<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop".to_string(),
+                    0xa2c
+                ),
+                ("democrate::fib".to_string(), 0x10),
+                ("std::sys::COUNTER".to_string(), 0x8),
+            ]
+        );
+    }
+
+    #[test]
+    fn crate_of_symbol() {
+        assert_eq!(symbol_crate("democrate::fib"), "democrate");
+        assert_eq!(
+            symbol_crate("<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop"),
+            "alloc"
+        );
+        assert_eq!(symbol_crate("<u64 as core::fmt::Debug>::fmt"), "core");
+        assert_eq!(
+            symbol_crate("<&mut W as core::fmt::Write>::write_str"),
+            "core"
+        );
+        assert_eq!(symbol_crate("main"), "[unknown]");
+    }
+}
@@ -0,0 +1,579 @@
+/// `ctm bisect`: find the first rust commit between a good and a bad rev which changes a
+/// crate's run duration or binary size. Toolchains are built at first parent commits with
+/// the patches of a configured toolchain, and measurements are kept in a state file, so an
+/// interrupted bisect resumes where it stopped
+use crate::build_toolchain::ToolChainOpts;
+use crate::config::{BuildMode, Config, CrateOpt, Profile, ToolchainConfig};
+use crate::plan::PlanStep;
+use crate::stats;
+use serde::{Deserialize, Serialize};
+
+mod patches;
+pub use patches::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ArgEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BisectMetric {
+    /// duration samples of a run
+    Duration,
+    /// binary size after strip
+    Size,
+}
+
+/// what is bisected, a state file is only resumed with the same options
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct BisectOpts {
+    pub krate: String,
+
+    /// run to sample, needed for duration
+    pub run: Option<String>,
+
+    /// toolchain whose patches are applied at each commit
+    pub toolchain: String,
+
+    pub profile: String,
+    pub metric: BisectMetric,
+
+    /// good and bad revs, resolved to commits before bisecting
+    pub good: String,
+    pub bad: String,
+
+    /// a commit is bad if metric changes more than this percent from good towards bad.
+    /// `None` means significantly at 95% by Welch's t-test and closer to bad than to good
+    /// for duration, and any change for size
+    pub threshold_pct: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BisectState {
+    pub opts: BisectOpts,
+
+    /// (patch file name, sha256) of the toolchain's patches, a state file of edited
+    /// patches is not resumed
+    #[serde(default)]
+    pub patches: Vec<(String, String)>,
+
+    /// first parent commits from good to bad, both included
+    pub commits: Vec<String>,
+
+    /// measured commits in the order they were tested
+    pub tested: Vec<TestedCommit>,
+
+    /// commits whose toolchain or crate failed to build or run, skipped like
+    /// `git bisect skip`
+    #[serde(default)]
+    pub untestable: Vec<UntestableCommit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestedCommit {
+    /// index in `BisectState.commits`
+    pub index: usize,
+
+    pub commit: String,
+
+    /// first line of commit message
+    pub subject: String,
+
+    /// duration samples in nanoseconds, or the binary size
+    pub values: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UntestableCommit {
+    /// index in `BisectState.commits`
+    pub index: usize,
+
+    pub commit: String,
+
+    /// first line of commit message
+    pub subject: String,
+
+    /// why measuring failed
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Good,
+    Bad,
+}
+
+impl BisectState {
+    fn values(&self, index: usize) -> Option<&[f64]> {
+        self.tested
+            .iter()
+            .find(|t| t.index == index)
+            .map(|t| t.values.as_slice())
+    }
+
+    fn is_untestable(&self, index: usize) -> bool {
+        self.untestable.iter().any(|u| u.index == index)
+    }
+
+    /// commit to test next between good and bad: the middle one, or the testable one
+    /// nearest to it. `None` if there is no testable commit left between them
+    fn next_commit(&self, good: usize, bad: usize) -> Option<usize> {
+        let mid = (good + bad) / 2;
+        (good + 1..bad)
+            .filter(|index| !self.is_untestable(*index))
+            .min_by_key(|index| index.abs_diff(mid))
+    }
+
+    /// values of good and bad commits, both must have been measured
+    pub fn endpoints(&self) -> (&[f64], &[f64]) {
+        (
+            self.values(0).unwrap(),
+            self.values(self.commits.len() - 1).unwrap(),
+        )
+    }
+
+    /// change of values from good towards bad in percent, negative if away from bad
+    pub fn change_pct(&self, values: &[f64]) -> f64 {
+        let (good, bad) = self.endpoints();
+        let (good_mean, bad_mean) = (stats::mean(good), stats::mean(bad));
+        let towards_bad = if bad_mean >= good_mean { 1.0 } else { -1.0 };
+        (stats::mean(values) - good_mean) / good_mean * 100.0 * towards_bad
+    }
+
+    /// whether values differ from good's towards bad enough to call the commit bad
+    pub fn verdict(&self, values: &[f64]) -> Verdict {
+        let (good, bad) = self.endpoints();
+        let change_pct = self.change_pct(values);
+        let towards_bad = if stats::mean(bad) >= stats::mean(good) {
+            1.0
+        } else {
+            -1.0
+        };
+
+        let changed = match (self.opts.threshold_pct, self.opts.metric) {
+            (Some(threshold_pct), _) => change_pct > threshold_pct,
+            // significant alone is too sensitive to noise between builds, it also has to
+            // be closer to bad than to good
+            (None, BisectMetric::Duration) => {
+                let closer_to_bad = change_pct > self.change_pct(bad) / 2.0;
+                match stats::welch_t(values, good) {
+                    Some((t, df)) => t * towards_bad > stats::t_critical_95(df) && closer_to_bad,
+                    None => closer_to_bad,
+                }
+            }
+            (None, BisectMetric::Size) => change_pct > 0.0,
+        };
+        if changed {
+            Verdict::Bad
+        } else {
+            Verdict::Good
+        }
+    }
+}
+
+/// result of a finished bisect
+pub struct BisectOutcome {
+    pub state: BisectState,
+
+    /// index of first bad commit in `state.commits`
+    pub first_bad: usize,
+}
+
+/// default state file, in build root
+pub fn default_state_path(opts: &BisectOpts, config: &Config) -> std::path::PathBuf {
+    let subject = match opts.metric {
+        BisectMetric::Duration => opts.run.clone().unwrap_or_default(),
+        BisectMetric::Size => "size".to_string(),
+    };
+    config
+        .global
+        .build_root()
+        .join(format!("bisect-{}-{}.json", opts.krate, subject))
+}
+
+/// resolve good and bad revs of opts to commits, returns first parent commits from good
+/// to bad, both included
+fn resolve(opts: &mut BisectOpts, config: &Config) -> anyhow::Result<Vec<String>> {
+    let rust_repo = config.global.rust_repo();
+    if !rust_repo.exists() {
+        anyhow::bail!("rust repo {rust_repo:?} is missing, run `ctm bootstrap` first");
+    }
+    let (good, bad) = (
+        format!("{}^{{commit}}", opts.good),
+        format!("{}^{{commit}}", opts.bad),
+    );
+    let good = cmd_lib::run_fun!(git -C $rust_repo rev-parse $good)?;
+    let bad = cmd_lib::run_fun!(git -C $rust_repo rev-parse $bad)?;
+
+    let range = format!("{good}..{bad}");
+    let mut commits = vec![good.clone()];
+    commits.extend(
+        cmd_lib::run_fun!(git -C $rust_repo rev-list --first-parent --reverse $range)?
+            .lines()
+            .map(str::to_string),
+    );
+    if commits.len() < 2 || commits.last() != Some(&bad) {
+        anyhow::bail!("{bad} is not a first parent descendant of {good}");
+    }
+
+    opts.good = good;
+    opts.bad = bad;
+    Ok(commits)
+}
+
+/// the toolchain measured at commit, with patches of the configured toolchain. Its name
+/// has a hash of the patches, so a kept toolchain isn't taken for one with other patches
+fn toolchain_at(
+    commit: &str,
+    patches: &[(String, String)],
+    base: &ToolchainConfig,
+    profile: &Profile,
+) -> anyhow::Result<ToolchainConfig> {
+    let mut name = format!("bisect-{}", &commit[..12.min(commit.len())]);
+    if !patches.is_empty() {
+        let hash = crate::metadata::sha256_of(&serde_json::to_string(patches)?)?;
+        name = format!("{name}-{}", &hash[..8]);
+    }
+    Ok(ToolchainConfig {
+        name,
+        patches: base.patches.clone(),
+        rust_rev: Some(commit.to_string()),
+        profiles: vec![profile.name.clone()],
+    })
+}
+
+/// bisect the first parent commits between good and bad, resuming from state file if it
+/// exists. Toolchains built for bisecting are removed after measuring unless
+/// `keep_toolchains`
+pub fn bisect(
+    mut opts: BisectOpts,
+    state_path: &std::path::Path,
+    keep_toolchains: bool,
+    config: &Config,
+) -> anyhow::Result<BisectOutcome> {
+    let krate = config.krate(&opts.krate)?;
+    let base = config.toolchain(&opts.toolchain)?;
+    let profile = config.profile(&opts.profile)?;
+    if let Some(run) = opts.run.as_ref() {
+        if !krate.runs.iter().any(|r| r.name.eq(run)) {
+            anyhow::bail!("run {run} not defined for crate {}", krate.name);
+        }
+    } else if opts.metric == BisectMetric::Duration {
+        anyhow::bail!("bisecting duration needs a run, pass --run");
+    }
+
+    let commits = resolve(&mut opts, config)?;
+    let patches = ToolChainOpts::from_config(base, &config.global, false).patch_hashes()?;
+    let mut state = match std::fs::read_to_string(state_path) {
+        Ok(content) => {
+            let state: BisectState = serde_json::from_str(&content)?;
+            if state.opts != opts || state.patches != patches {
+                anyhow::bail!(
+                    "state file {state_path:?} is of another bisect, remove it or pass another --state"
+                );
+            }
+            log::info!(
+                "resuming bisect with {} commits tested from {:?}",
+                state.tested.len() + state.untestable.len(),
+                state_path
+            );
+            state
+        }
+        Err(_) => BisectState {
+            opts,
+            patches,
+            commits,
+            tested: vec![],
+            untestable: vec![],
+        },
+    };
+
+    // values of commit, `None` if it is untestable
+    let measured = |state: &mut BisectState, index: usize| -> anyhow::Result<Option<Vec<f64>>> {
+        if let Some(values) = state.values(index) {
+            return Ok(Some(values.to_vec()));
+        }
+        if state.is_untestable(index) {
+            return Ok(None);
+        }
+        let commit = state.commits[index].clone();
+        log::info!(
+            "bisect: measuring commit {} of {}: {}",
+            index,
+            state.commits.len() - 1,
+            commit
+        );
+        let toolchain = toolchain_at(&commit, &state.patches, base, profile)?;
+        let run = match state.opts.metric {
+            BisectMetric::Duration => state.opts.run.as_deref(),
+            BisectMetric::Size => None,
+        };
+        let values =
+            measure(krate, &toolchain, profile, run, config).map(|(binary_size, durations)| {
+                match run {
+                    Some(_) => durations,
+                    None => vec![binary_size as f64],
+                }
+            });
+        if !keep_toolchains {
+            remove_toolchain(krate, &toolchain, profile, config)?;
+        }
+
+        let rust_repo = config.global.rust_repo();
+        let subject = cmd_lib::run_fun!(git -C $rust_repo log -1 --format=%s $commit)?;
+        let values = match values {
+            Ok(values) => {
+                state.tested.push(TestedCommit {
+                    index,
+                    commit,
+                    subject,
+                    values: values.clone(),
+                });
+                Some(values)
+            }
+            // e.g: a patch doesn't apply or the toolchain doesn't build at this commit
+            Err(e) => {
+                log::warn!("bisect: commit {commit} is untestable, skipped: {e:#}");
+                state.untestable.push(UntestableCommit {
+                    index,
+                    commit,
+                    subject,
+                    error: format!("{e:#}"),
+                });
+                None
+            }
+        };
+        std::fs::write(state_path, serde_json::to_string_pretty(&state)?)?;
+        Ok(values)
+    };
+
+    let (mut good, mut bad) = (0, state.commits.len() - 1);
+    let endpoint = |state: &mut BisectState, index: usize| -> anyhow::Result<Vec<f64>> {
+        measured(state, index)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "commit {} to bisect from is untestable, see {state_path:?}",
+                state.commits[index]
+            )
+        })
+    };
+    endpoint(&mut state, good)?;
+    let bad_values = endpoint(&mut state, bad)?;
+    if state.verdict(&bad_values) != Verdict::Bad {
+        anyhow::bail!(
+            "bad commit changes {:.2}% from good, which is not a regression to bisect",
+            state.change_pct(&bad_values)
+        );
+    }
+
+    while let Some(mid) = state.next_commit(good, bad) {
+        let values = match measured(&mut state, mid)? {
+            Some(values) => values,
+            None => continue,
+        };
+        let verdict = state.verdict(&values);
+        match verdict {
+            Verdict::Good => good = mid,
+            Verdict::Bad => bad = mid,
+        }
+        log::info!(
+            "bisect: commit {} is {:?}, {:.2}% from good, {} commits left",
+            state.commits[mid],
+            verdict,
+            state.change_pct(&values),
+            bad - good - 1
+        );
+    }
+
+    if bad - good > 1 {
+        log::warn!(
+            "bisect: only untestable commits {} are left, the first bad commit is one of them or {}",
+            state.commits[good + 1..bad].join(", "),
+            state.commits[bad]
+        );
+    } else {
+        log::info!("bisect: first bad commit is {}", state.commits[bad]);
+    }
+    Ok(BisectOutcome {
+        state,
+        first_bad: bad,
+    })
+}
+
+/// build toolchain and crate, returns the binary size after strip, and duration samples
+/// which didn't time out if a run is given
+fn measure(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    run: Option<&str>,
+    config: &Config,
+) -> anyhow::Result<(u64, Vec<f64>)> {
+    let toolchain_opts = ToolChainOpts::from_config(toolchain, &config.global, false);
+    crate::build_toolchain::build_toolchain(&config.global.rust_repo(), &toolchain_opts)?;
+
+    match run {
+        Some(run) => {
+            let filter = crate::utils::CellFilter {
+                runs: vec![run.to_string()],
+                ..Default::default()
+            };
+            let results =
+                crate::run::run_cmd_step(krate, toolchain, profile, config, &filter, true)?;
+            let binary_size = results.first().map(|r| r.binary_size).unwrap_or_default();
+            let durations = results
+                .iter()
+                .filter(|r| !r.timed_out)
+                .map(|r| r.duration_ns as f64)
+                .collect::<Vec<_>>();
+            if durations.is_empty() {
+                anyhow::bail!("every sample of {run} with {} timed out", toolchain.name);
+            }
+            Ok((binary_size, durations))
+        }
+        None => {
+            let artifact = crate::build_crate::build_crate_step(
+                krate,
+                toolchain,
+                profile,
+                BuildMode::Release,
+                config,
+                false,
+            )?;
+            let output_path = artifact.output_path.unwrap();
+            Ok((std::fs::metadata(output_path)?.len(), vec![]))
+        }
+    }
+}
+
+/// plan of `measure` for each toolchain: build and link it, build crate, then sample the
+/// run if any. Each step comes with the index of its toolchain
+fn plan_measure(
+    krate: &CrateOpt,
+    toolchains: &[ToolchainConfig],
+    profile: &Profile,
+    run: Option<&str>,
+    config: &Config,
+) -> anyhow::Result<Vec<(usize, PlanStep)>> {
+    let toolchain_opts = toolchains
+        .iter()
+        .map(|toolchain| ToolChainOpts::from_config(toolchain, &config.global, false))
+        .collect::<Vec<_>>();
+
+    // build and link steps, two for each toolchain
+    let mut steps =
+        crate::build_toolchain::plan_build_toolchain(&config.global.rust_repo(), &toolchain_opts)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, step)| (i / 2, step))
+            .collect::<Vec<_>>();
+    for (index, toolchain) in toolchains.iter().enumerate() {
+        let build = crate::build_crate::plan_build_crate_step(
+            krate,
+            toolchain,
+            profile,
+            BuildMode::Release,
+            config,
+            false,
+        )?;
+        steps.push((index, build));
+        if let Some(run) = run {
+            let filter = crate::utils::CellFilter {
+                runs: vec![run.to_string()],
+                ..Default::default()
+            };
+            for step in crate::run::plan_run_samples(krate, toolchain, profile, config, &filter)? {
+                steps.push((index, step));
+            }
+        }
+    }
+    Ok(steps)
+}
+
+/// remove a toolchain built for bisecting, and the crate's target folder of it
+fn remove_toolchain(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let opts = ToolChainOpts::from_config(toolchain, &config.global, false);
+    let (name, folder) = (&toolchain.name, opts.toolchain_folder());
+    let target_folder = crate::utils::target_folder(krate, profile, toolchain, config);
+    log::debug!("removing toolchain {name}");
+    cmd_lib::run_cmd!(
+        rustup toolchain uninstall $name;
+        rm -rf $folder $target_folder;
+    )?;
+    Ok(())
+}
+
+/// plan of `bisect`: the good and bad commits, then about log2 of the rest
+pub fn plan_bisect(
+    mut opts: BisectOpts,
+    state_path: &std::path::Path,
+    keep_toolchains: bool,
+    config: &Config,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let krate = config.krate(&opts.krate)?;
+    let base = config.toolchain(&opts.toolchain)?;
+    let profile = config.profile(&opts.profile)?;
+    let commits = resolve(&mut opts, config)?;
+    let patches = ToolChainOpts::from_config(base, &config.global, false).patch_hashes()?;
+
+    let state = std::fs::read_to_string(state_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<BisectState>(&content).ok())
+        .filter(|state| state.opts == opts && state.patches == patches);
+    let tested = |index: usize| {
+        state
+            .as_ref()
+            .map(|s| s.values(index).is_some() || s.is_untestable(index))
+            .unwrap_or(false)
+    };
+
+    let endpoints = [0, commits.len() - 1];
+    let toolchains = endpoints
+        .iter()
+        .map(|index| toolchain_at(&commits[*index], &patches, base, profile))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let toolchain_disk = crate::plan::estimate_new_folder(
+        &ToolChainOpts::from_config(&toolchains[0], &config.global, false)
+            .toolchains_root
+            .join("bisect-next"),
+    )?
+    .unwrap_or(crate::plan::TOOLCHAIN_ESTIMATE);
+
+    let run = match opts.metric {
+        BisectMetric::Duration => opts.run.as_deref(),
+        BisectMetric::Size => None,
+    };
+    let mut steps = vec![];
+    for (i, mut step) in plan_measure(krate, &toolchains, profile, run, config)? {
+        if tested(endpoints[i]) {
+            step.cached = Some(format!("measured in {state_path:?}"));
+        }
+        steps.push(step);
+    }
+    let remaining = commits.len().saturating_sub(2);
+    let rounds = (remaining + 1).next_power_of_two().trailing_zeros();
+    let mut rest = PlanStep::new(format!(
+        "bisect {remaining} commits between {} and {}",
+        opts.good, opts.bad
+    ));
+    rest.notes.push(format!(
+        "about {rounds} more toolchains are built and measured the same way"
+    ));
+    if !keep_toolchains {
+        rest.notes
+            .push("each toolchain is removed after measuring".to_string());
+    }
+    rest.notes.push(
+        "a commit failing to build or run is untestable, a neighbouring one is tested instead"
+            .to_string(),
+    );
+    rest.disk_bytes = Some(if keep_toolchains {
+        toolchain_disk * rounds as u64
+    } else {
+        0
+    });
+    steps.push(rest);
+
+    Ok(steps)
+}
@@ -0,0 +1,238 @@
+/// `ctm bisect-patches`: find which patch of a toolchain's series changes a crate's run
+/// duration or binary size. A toolchain is built for each prefix of the patch list, from
+/// none to all of them, at the toolchain's rust rev, and the crate is measured with each
+use super::{measure, plan_measure, remove_toolchain};
+use crate::build_toolchain::{ToolChainOpts, ToolchainStamp};
+use crate::config::{Config, Profile, ToolchainConfig};
+use crate::plan::PlanStep;
+use serde::{Deserialize, Serialize};
+
+/// what is measured, a state file is only resumed with the same options
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PatchSeriesOpts {
+    pub krate: String,
+
+    /// run to sample, only binary size is measured without
+    pub run: Option<String>,
+
+    /// toolchain whose patch series is measured
+    pub toolchain: String,
+
+    pub profile: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PatchSeriesState {
+    pub opts: PatchSeriesOpts,
+
+    /// resolved rust commit and patches the series is measured with, a state file of
+    /// edited patches is not resumed
+    pub stamp: ToolchainStamp,
+
+    /// measured prefixes in the order they were measured
+    pub measured: Vec<MeasuredPrefix>,
+
+    /// prefixes whose toolchain or crate failed to build or run
+    #[serde(default)]
+    pub untestable: Vec<UntestablePrefix>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeasuredPrefix {
+    /// number of patches applied, from the start of the series
+    pub applied: usize,
+
+    /// binary size after strip
+    pub binary_size: u64,
+
+    /// duration samples in nanoseconds, empty without a run
+    pub durations: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UntestablePrefix {
+    /// number of patches applied, from the start of the series
+    pub applied: usize,
+
+    /// why measuring failed
+    pub error: String,
+}
+
+impl PatchSeriesState {
+    /// measured prefixes, in patch order
+    pub fn prefixes(&self) -> Vec<&MeasuredPrefix> {
+        let mut prefixes = self.measured.iter().collect::<Vec<_>>();
+        prefixes.sort_by_key(|p| p.applied);
+        prefixes
+    }
+
+    fn is_done(&self, applied: usize) -> bool {
+        self.measured.iter().any(|p| p.applied == applied)
+            || self.untestable.iter().any(|p| p.applied == applied)
+    }
+}
+
+/// default state file, in build root
+pub fn default_patches_state_path(opts: &PatchSeriesOpts, config: &Config) -> std::path::PathBuf {
+    let subject = opts.run.clone().unwrap_or_else(|| "size".to_string());
+    config.global.build_root().join(format!(
+        "bisect-patches-{}-{}-{}.json",
+        opts.toolchain, opts.krate, subject
+    ))
+}
+
+/// stamp of toolchain with its rust rev resolved to a commit
+fn series_stamp(toolchain: &ToolchainConfig, config: &Config) -> anyhow::Result<ToolchainStamp> {
+    let rust_repo = config.global.rust_repo();
+    if !rust_repo.exists() {
+        anyhow::bail!("rust repo {rust_repo:?} is missing, run `ctm bootstrap` first");
+    }
+    ToolChainOpts::from_config(toolchain, &config.global, false).expected_stamp(&rust_repo)
+}
+
+/// the toolchain measured with the first `applied` patches of the series
+fn toolchain_with(
+    applied: usize,
+    base: &ToolchainConfig,
+    stamp: &ToolchainStamp,
+    profile: &Profile,
+) -> ToolchainConfig {
+    ToolchainConfig {
+        name: format!("{}-p{applied}", base.name),
+        patches: base.patches[..applied].to_vec(),
+        rust_rev: Some(stamp.rust_rev.clone()),
+        profiles: vec![profile.name.clone()],
+    }
+}
+
+/// measure every prefix of toolchain's patch series, resuming from state file if it
+/// exists. Toolchains built for measuring are removed after measuring unless
+/// `keep_toolchains`. A prefix which fails to build or run is untestable and skipped, like
+/// an untestable commit of `bisect`, the next prefix then measures its patch too. The
+/// unpatched toolchain must build, everything is measured against it
+pub fn bisect_patches(
+    opts: PatchSeriesOpts,
+    state_path: &std::path::Path,
+    keep_toolchains: bool,
+    config: &Config,
+) -> anyhow::Result<PatchSeriesState> {
+    let krate = config.krate(&opts.krate)?;
+    let base = config.toolchain(&opts.toolchain)?;
+    let profile = config.profile(&opts.profile)?;
+    if let Some(run) = opts.run.as_ref() {
+        if !krate.runs.iter().any(|r| r.name.eq(run)) {
+            anyhow::bail!("run {run} not defined for crate {}", krate.name);
+        }
+    }
+    if base.patches.is_empty() {
+        anyhow::bail!("toolchain {} has no patches", base.name);
+    }
+
+    let stamp = series_stamp(base, config)?;
+    let mut state = match std::fs::read_to_string(state_path) {
+        Ok(content) => {
+            let state: PatchSeriesState = serde_json::from_str(&content)?;
+            if state.opts != opts || state.stamp != stamp {
+                anyhow::bail!(
+                    "state file {state_path:?} is of another patch series, remove it or pass another --state"
+                );
+            }
+            log::info!(
+                "resuming with {} prefixes measured from {:?}",
+                state.measured.len() + state.untestable.len(),
+                state_path
+            );
+            state
+        }
+        Err(_) => PatchSeriesState {
+            opts,
+            stamp,
+            measured: vec![],
+            untestable: vec![],
+        },
+    };
+
+    for applied in 0..=base.patches.len() {
+        if state.is_done(applied) {
+            continue;
+        }
+        log::info!(
+            "bisect-patches: measuring {} with {applied} of {} patches",
+            base.name,
+            base.patches.len()
+        );
+        let toolchain = toolchain_with(applied, base, &state.stamp, profile);
+        let measured = measure(
+            krate,
+            &toolchain,
+            profile,
+            state.opts.run.as_deref(),
+            config,
+        );
+        if !keep_toolchains {
+            remove_toolchain(krate, &toolchain, profile, config)?;
+        }
+        match measured {
+            Ok((binary_size, durations)) => state.measured.push(MeasuredPrefix {
+                applied,
+                binary_size,
+                durations,
+            }),
+            Err(e) if applied == 0 => {
+                return Err(e.context(format!("unpatched toolchain {}", base.name)));
+            }
+            // e.g: a patch doesn't apply on the ones before it
+            Err(e) => {
+                log::warn!(
+                    "bisect-patches: {applied} of {} patches are untestable, skipped: {e:#}",
+                    base.patches.len()
+                );
+                state.untestable.push(UntestablePrefix {
+                    applied,
+                    error: format!("{e:#}"),
+                });
+            }
+        }
+        std::fs::write(state_path, serde_json::to_string_pretty(&state)?)?;
+    }
+    Ok(state)
+}
+
+/// plan of `bisect_patches`: every prefix of the patch series
+pub fn plan_bisect_patches(
+    opts: PatchSeriesOpts,
+    state_path: &std::path::Path,
+    keep_toolchains: bool,
+    config: &Config,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let krate = config.krate(&opts.krate)?;
+    let base = config.toolchain(&opts.toolchain)?;
+    let profile = config.profile(&opts.profile)?;
+    let stamp = series_stamp(base, config)?;
+
+    let state = std::fs::read_to_string(state_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<PatchSeriesState>(&content).ok())
+        .filter(|state| state.opts == opts && state.stamp == stamp);
+    let measured = |applied: usize| state.as_ref().map(|s| s.is_done(applied)).unwrap_or(false);
+
+    let toolchains = (0..=base.patches.len())
+        .map(|applied| toolchain_with(applied, base, &stamp, profile))
+        .collect::<Vec<_>>();
+    let mut steps = vec![];
+    let mut noted = vec![false; toolchains.len()];
+    for (applied, mut step) in
+        plan_measure(krate, &toolchains, profile, opts.run.as_deref(), config)?
+    {
+        if measured(applied) {
+            step.cached = Some(format!("measured in {state_path:?}"));
+        }
+        // steps of a toolchain start with building it
+        if !keep_toolchains && !std::mem::replace(&mut noted[applied], true) {
+            step.notes
+                .push("toolchain is removed after measuring".to_string());
+        }
+        steps.push(step);
+    }
+    Ok(steps)
+}
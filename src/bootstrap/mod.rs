@@ -1,21 +1,41 @@
+use crate::plan::{quote, PlanStep};
+
 /// bootstrap
 /// 1. clone rust
 /// 2. git submodule update
 pub fn bootstrap(config: &crate::config::Config) -> anyhow::Result<()> {
+    for step in plan_bootstrap(config) {
+        match step.cached.as_ref() {
+            Some(reason) => log::info!("skip {}, {}", step.title, reason),
+            None => {
+                step.execute()?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// plan of `bootstrap`
+pub fn plan_bootstrap(config: &crate::config::Config) -> Vec<PlanStep> {
     let rust_repo = config.global.rust_repo();
 
-    if !rust_repo.exists() {
-        log::info!("cloning rust into {:?}", rust_repo);
-        cmd_lib::run_cmd!(
-            git clone "https://github.com/rust-lang/rust.git" $rust_repo;
-        )?;
-    } else {
-        log::info!("skip cloning {:?} already exists", rust_repo);
+    let mut clone = PlanStep::new("clone rust");
+    if rust_repo.exists() {
+        clone.cached = Some(format!("{:?} already exists", rust_repo));
     }
-    cmd_lib::run_cmd!(
-        cd $rust_repo;
-        git submodule update --init --recursive;
-    )?;
+    clone.cmds.push(format!(
+        "git clone https://github.com/rust-lang/rust.git {}",
+        quote(rust_repo.to_str().unwrap())
+    ));
+    clone.disk_bytes = Some(crate::plan::RUST_REPO_ESTIMATE);
 
-    Ok(())
+    let mut submodule = PlanStep::new("update rust submodules");
+    submodule.cwd = Some(rust_repo);
+    submodule
+        .cmds
+        .push("git submodule update --init --recursive".to_string());
+    submodule.disk_bytes = Some(0);
+
+    vec![clone, submodule]
 }
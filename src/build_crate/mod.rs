@@ -1,30 +1,210 @@
+use crate::config::{BuildMode, Config, CrateOpt, Profile, StripMode, ToolchainConfig};
+use crate::plan::{quote, PlanStep};
 use crate::utils;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug)]
 pub struct Artifact {
     pub toolchain: String,
     pub profile: String,
     pub crate_name: String,
-    pub output_path: std::path::PathBuf,
+    pub mode: BuildMode,
+    /// how long the (re)build takes
+    pub build_duration_ms: u64,
+    /// only release mode produces the output artifact, the stripped copy of cargo's output
+    pub output_path: Option<std::path::PathBuf>,
+    /// cargo's output, which is never stripped
+    pub unstripped_path: Option<std::path::PathBuf>,
+    /// release build skipped because the artifact is up to date, `build_duration_ms` is
+    /// from the build which produced it
+    pub cached: bool,
+}
+
+/// what a release artifact is built from besides the toolchain, recorded next to it in
+/// target folder. The artifact is stale once any of them or the toolchain changes
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// environment variables of build, from profile
+    pub environ: BTreeMap<String, String>,
+
+    /// commit of crate checkout, with `-dirty-<hash of changes>` suffix if it has
+    /// uncommitted changes
+    pub crate_commit: Option<String>,
+
+    /// newest file of a local crate source which is not a git repo or has no commit yet,
+    /// unix seconds
+    pub source_mtime: Option<u64>,
+
+    pub build_cmd: String,
+    pub strip: StripMode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FingerprintFile {
+    /// kept apart from fingerprint, as a changed toolchain needs a clean build
+    toolchain_hash: String,
+
+    fingerprint: Fingerprint,
+
+    /// kept to report cached builds
+    build_duration_ms: u64,
+}
+
+/// sha256 of `rustc -vV` and the stamp of toolchains built by ctm. A toolchain rebuilt
+/// with other patches at the same rust rev has the same `rustc -vV`, which cargo doesn't
+/// notice, so a changed hash makes a clean build
+pub fn toolchain_hash(toolchain: &ToolchainConfig, config: &Config) -> anyhow::Result<String> {
+    let toolchain_name = &toolchain.name;
+    let rustc_version = cmd_lib::run_fun!(RUSTUP_TOOLCHAIN=$toolchain_name rustc -vV)?;
+    let stamp =
+        crate::build_toolchain::ToolChainOpts::from_config(toolchain, &config.global, false)
+            .stamp()
+            .map(|stamp| serde_json::to_string(&stamp))
+            .transpose()?
+            .unwrap_or_default();
+    crate::metadata::sha256_of(&format!("{rustc_version}\n{stamp}"))
+}
+
+/// fingerprint of the release artifact if built now
+pub fn fingerprint(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> anyhow::Result<Fingerprint> {
+    let crate_commit = checkout_commit(&utils::crate_folder(krate, config))?;
+    // the checkout of a local crate is touched by incremental builds, look at the source
+    let source_mtime = match utils::crate_source_path(krate, config) {
+        Some(source) if crate_commit.is_none() => {
+            utils::newest_mtime(&source, &["target", ".git"])?
+                .map(|t| t.duration_since(std::time::UNIX_EPOCH))
+                .transpose()?
+                .map(|d| d.as_secs())
+        }
+        _ => None,
+    };
+
+    Ok(Fingerprint {
+        environ: utils::build_environ(krate, profile, toolchain, config)
+            .into_iter()
+            .collect(),
+        crate_commit,
+        source_mtime,
+        build_cmd: krate.release_build_cmd(),
+        strip: krate.strip.or(profile.strip).unwrap_or_default(),
+    })
+}
+
+/// commit of a git checkout, `None` if it isn't a git repo or has no commit yet, e.g: made
+/// by `cargo new`. Uncommitted changes, untracked files included, are hashed into it, so
+/// every further edit of a dirty checkout changes it too
+fn checkout_commit(folder: &std::path::Path) -> anyhow::Result<Option<String>> {
+    if !folder.join(".git").exists() {
+        return Ok(None);
+    }
+    let commit = match cmd_lib::run_fun!(git -C $folder rev-parse --verify -q HEAD) {
+        Ok(commit) => commit,
+        Err(_) => return Ok(None),
+    };
+    // target folders of ctm are inside the checkout
+    let status = cmd_lib::run_fun!(git -C $folder status --porcelain -- . ":!target")?;
+    if status.trim().is_empty() {
+        return Ok(Some(commit));
+    }
+
+    let mut changes = cmd_lib::run_fun!(git -C $folder diff --binary HEAD)?;
+    let untracked =
+        cmd_lib::run_fun!(git -C $folder ls-files -z --others --exclude-standard -- . ":!target")?;
+    for path in untracked.split('\0').filter(|p| !p.is_empty()) {
+        let hash = crate::metadata::sha256(&folder.join(path))?;
+        changes.push_str(&format!("\n{path} {hash}"));
+    }
+    let hash = crate::metadata::sha256_of(&changes)?;
+    Ok(Some(format!("{commit}-dirty-{hash}")))
+}
+
+fn fingerprint_path(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> std::path::PathBuf {
+    utils::target_folder(krate, profile, toolchain, config).join("ctm-fingerprint.json")
+}
+
+fn read_fingerprint(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> Option<FingerprintFile> {
+    let content = std::fs::read_to_string(fingerprint_path(krate, toolchain, profile, config));
+    serde_json::from_str(&content.ok()?).ok()
+}
+
+/// whether release artifact exists and is built from the current toolchain, profile and
+/// source
+pub fn is_up_to_date(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> anyhow::Result<bool> {
+    let artifact = utils::artifact_path(krate, profile, toolchain, config);
+    let recorded = match read_fingerprint(krate, toolchain, profile, config) {
+        Some(recorded) if artifact.exists() => recorded,
+        _ => return Ok(false),
+    };
+    Ok(
+        recorded.toolchain_hash == toolchain_hash(toolchain, config)?
+            && recorded.fingerprint == fingerprint(krate, toolchain, profile, config)?,
+    )
+}
+
+/// whether release build starts with `cargo clean --release`: if asked, if the toolchain
+/// changed since the recorded build, or if cargo's output was stripped in place by an
+/// older ctm which kept no stripped copy
+fn release_clean(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+    clean: bool,
+) -> anyhow::Result<bool> {
+    // without a recorded build the toolchain may not even be built yet, e.g: in a plan
+    let toolchain_changed = match read_fingerprint(krate, toolchain, profile, config) {
+        Some(recorded) => recorded.toolchain_hash != toolchain_hash(toolchain, config)?,
+        None => false,
+    };
+    let stripped_in_place = utils::cargo_output_path(krate, profile, toolchain, config).exists()
+        && !utils::artifact_path(krate, profile, toolchain, config).exists();
+    Ok(clean || toolchain_changed || stripped_in_place)
 }
 
 pub fn build_crate_for_all_profile(
-    krate: &crate::config::CrateOpt,
-    config: &crate::config::Config,
+    krate: &CrateOpt,
+    config: &Config,
+    filter: &crate::utils::CellFilter,
+    clean: bool,
 ) -> anyhow::Result<Vec<Artifact>> {
     let mut artifacts = vec![];
     // build the crate for each toolchain and profile
     for toolchain in config.toolchains.iter() {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
         for profile in toolchain.profiles.iter() {
-            let profile = config
-                .profiles
-                .iter()
-                .filter(|p| p.name.eq(profile))
-                .nth(0)
-                .unwrap();
-
-            let artifact = build_crate_step(krate, toolchain, profile, config)?;
-            artifacts.push(artifact);
+            if !filter.profile(profile) {
+                continue;
+            }
+            let profile = config.profile(profile)?;
+
+            for mode in krate.build_modes.iter() {
+                let artifact = build_crate_step(krate, toolchain, profile, *mode, config, clean)?;
+                artifacts.push(artifact);
+            }
         }
     }
 
@@ -32,82 +212,311 @@ pub fn build_crate_for_all_profile(
 }
 
 pub fn build_crate_step(
-    krate: &crate::config::CrateOpt,
-    toolchain: &crate::config::ToolchainConfig,
-    profile: &crate::config::Profile,
-    config: &crate::config::Config,
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    mode: BuildMode,
+    config: &Config,
+    clean: bool,
 ) -> anyhow::Result<Artifact> {
-    let name = krate.name.clone();
-    let git = krate.git.clone();
-    let path = krate.path.clone();
-    let build_root = config.global.build_root().clone();
-
-    cmd_lib::run_cmd!(
-        mkdir -p $build_root;
-    )?;
-
-    let folder_name = name;
-    let folder_path = std::path::PathBuf::from(&build_root).join(&folder_name);
-
-    if !std::path::PathBuf::from(&folder_path).exists() {
-        if let Some(git) = git {
-            cmd_lib::run_cmd!(
-                cd $build_root;
-                git clone $git $folder_name;
-            )?;
-        } else if let Some(path) = path {
-            cmd_lib::run_cmd!(
-                cd $build_root;
-                cp -R $path $folder_name;
-            )?;
-        } else {
-            anyhow::bail!("Neither git nor path provided");
-        }
-    }
-
-    // create target foldr for each toolchain, to make build artifact
-    // more easier
-    let target_folder = utils::target_folder(krate, profile, toolchain, config);
+    // checkout, or pick up changes of local crate source
+    utils::checkout_crate(krate, config)?;
 
-    {
-        let target_folder = target_folder.to_str().unwrap().to_string();
-        let toolchain_name = toolchain.name.clone();
-
-        let mut environs = profile.environ.clone();
-        environs.insert("CARGO_TARGET_DIR".to_string(), target_folder);
-        environs.insert("RUSTUP_TOOLCHAIN".to_string(), toolchain_name);
-
-        let build_cmd = krate
-            .build_cmd
-            .as_ref()
-            .cloned()
-            .unwrap_or_else(|| "cargo build --release".to_string());
-
-        let output = std::process::Command::new("bash")
-            .stdout(std::process::Stdio::inherit())
-            .stderr(std::process::Stdio::inherit())
-            .envs(environs)
-            .current_dir(folder_path)
-            .args(&["-c", &build_cmd])
-            .output()?;
-        if !output.status.success() {
-            anyhow::bail!("failed to build with output: {output:?}");
+    let fingerprint = match mode {
+        BuildMode::Release => Some((
+            toolchain_hash(toolchain, config)?,
+            fingerprint(krate, toolchain, profile, config)?,
+        )),
+        BuildMode::Check | BuildMode::Debug | BuildMode::Incremental => None,
+    };
+    if let (Some((toolchain_hash, fingerprint)), false) = (fingerprint.as_ref(), clean) {
+        let output_path = utils::artifact_path(krate, profile, toolchain, config);
+        match read_fingerprint(krate, toolchain, profile, config) {
+            Some(recorded)
+                if recorded.toolchain_hash == *toolchain_hash
+                    && recorded.fingerprint == *fingerprint
+                    && output_path.exists() =>
+            {
+                log::info!(
+                    "skip building {} with {}_{}, up to date",
+                    krate.name,
+                    toolchain.name,
+                    profile.name
+                );
+                return Ok(Artifact {
+                    toolchain: toolchain.name.clone(),
+                    profile: profile.name.clone(),
+                    crate_name: krate.name.clone(),
+                    mode,
+                    build_duration_ms: recorded.build_duration_ms,
+                    unstripped_path: Some(utils::cargo_output_path(
+                        krate, profile, toolchain, config,
+                    )),
+                    output_path: Some(output_path),
+                    cached: true,
+                });
+            }
+            _ => {}
         }
     }
 
-    let output_path = target_folder.join(krate.output_path.clone());
+    let clean = match mode {
+        BuildMode::Release => release_clean(krate, toolchain, profile, config, clean)?,
+        BuildMode::Check | BuildMode::Debug | BuildMode::Incremental => clean,
+    };
+    let duration = build_step(krate, toolchain, profile, mode, config, clean)?.execute_timed()?;
+
+    let output_path = match mode {
+        BuildMode::Release => Some((
+            utils::artifact_path(krate, profile, toolchain, config),
+            utils::cargo_output_path(krate, profile, toolchain, config),
+        )),
+        BuildMode::Check | BuildMode::Debug | BuildMode::Incremental => None,
+    };
+    let (output_path, unstripped_path) = output_path.unzip();
 
-    // strip the binary
-    {
-        std::process::Command::new("strip")
-            .args(&[output_path.as_os_str()])
-            .output()?;
+    if let Some((toolchain_hash, fingerprint)) = fingerprint {
+        let file = FingerprintFile {
+            toolchain_hash,
+            fingerprint,
+            build_duration_ms: duration.as_millis() as u64,
+        };
+        std::fs::write(
+            fingerprint_path(krate, toolchain, profile, config),
+            serde_json::to_string_pretty(&file)?,
+        )?;
     }
 
     Ok(Artifact {
         toolchain: toolchain.name.clone(),
         profile: profile.name.clone(),
         crate_name: krate.name.clone(),
+        mode,
+        build_duration_ms: duration.as_millis() as u64,
         output_path,
+        unstripped_path,
+        cached: false,
     })
 }
+
+/// the step building one mode of a cell, in crate checkout with the cell's build environ
+fn build_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    mode: BuildMode,
+    config: &Config,
+    clean: bool,
+) -> anyhow::Result<PlanStep> {
+    let target_folder = utils::target_folder(krate, profile, toolchain, config);
+    let mut step = PlanStep::new(format!(
+        "build-crate {} {}_{} {}",
+        krate.name,
+        toolchain.name,
+        profile.name,
+        format!("{mode:?}").to_lowercase()
+    ));
+    step.cwd = Some(utils::crate_folder(krate, config));
+    step.env = utils::build_environ(krate, profile, toolchain, config)
+        .into_iter()
+        .collect();
+
+    // only release builds can be skipped as up to date, other modes are always timed from
+    // a clean dev profile
+    if clean || mode != BuildMode::Release {
+        step.cmds.push(clean_cmd(mode).to_string());
+    }
+    let build_cmd = krate.mode_build_cmd(mode)?;
+    match mode {
+        BuildMode::Check | BuildMode::Debug => {
+            step.timed = Some(step.cmds.len());
+            step.cmds.push(build_cmd);
+        }
+        BuildMode::Release => {
+            step.timed = Some(step.cmds.len());
+            step.cmds.push(build_cmd);
+
+            // strip into a copy, a no-op rebuild or another strip mode then strips
+            // cargo's unstripped output again
+            let artifact_path = utils::artifact_path(krate, profile, toolchain, config);
+            let (output_path, artifact_path, artifact_folder) = (
+                quote(target_folder.join(&krate.output_path).to_str().unwrap()),
+                quote(artifact_path.to_str().unwrap()),
+                quote(artifact_path.parent().unwrap().to_str().unwrap()),
+            );
+            step.cmds.push(format!("mkdir -p {artifact_folder}"));
+            let strip = krate.strip.or(profile.strip).unwrap_or_default();
+            step.cmds.push(match strip_args(strip) {
+                Some(args) => ["strip"]
+                    .into_iter()
+                    .chain(args)
+                    .chain(["-o", &artifact_path, &output_path])
+                    .join(" "),
+                None => format!("cp {output_path} {artifact_path}"),
+            });
+        }
+        BuildMode::Incremental => {
+            step.cmds.push(build_cmd.clone());
+            let patch_file = krate
+                .incremental
+                .patch
+                .as_ref()
+                .map(|p| config.global.to_absolute_path(p));
+            if let Some(patch_file) = patch_file.as_ref() {
+                let patch_file = quote(patch_file.to_str().unwrap());
+                step.cmds.push(format!("git apply {patch_file}"));
+                step.cleanup.push(format!("git apply -R {patch_file}"));
+            }
+            step.cmds.push(touch_cmd(krate));
+            step.timed = Some(step.cmds.len());
+            step.cmds.push(build_cmd);
+        }
+    }
+    Ok(step)
+}
+
+/// args of `strip` for strip mode, `None` if not stripped
+fn strip_args(strip: StripMode) -> Option<Vec<&'static str>> {
+    match strip {
+        StripMode::None => None,
+        StripMode::Debuginfo => Some(vec!["--strip-debug"]),
+        StripMode::All => Some(vec![]),
+    }
+}
+
+fn clean_cmd(mode: BuildMode) -> &'static str {
+    match mode {
+        BuildMode::Release => "cargo clean --release",
+        BuildMode::Check | BuildMode::Debug | BuildMode::Incremental => "cargo clean --profile dev",
+    }
+}
+
+/// cmd touching a source file before the timed rebuild of incremental mode, `src/main.rs`
+/// or `src/lib.rs` unless configured
+fn touch_cmd(krate: &CrateOpt) -> String {
+    match krate.incremental.touch.as_ref() {
+        Some(touch) => format!("touch {}", quote(touch)),
+        None => "if [ -e src/main.rs ]; then touch src/main.rs; elif [ -e src/lib.rs ]; then touch src/lib.rs; else echo 'no source file to touch' >&2; exit 1; fi".to_string(),
+    }
+}
+
+/// plan of `build_crate_for_all_profile`
+pub fn plan_build_crate_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+    filter: &crate::utils::CellFilter,
+    clean: bool,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let mut steps = vec![utils::plan_checkout(krate, config)?];
+    for toolchain in config.toolchains.iter() {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
+        for profile in toolchain.profiles.iter() {
+            if !filter.profile(profile) {
+                continue;
+            }
+            let profile = config.profile(profile)?;
+
+            for mode in krate.build_modes.iter() {
+                steps.push(plan_build_crate_step(
+                    krate, toolchain, profile, *mode, config, clean,
+                )?);
+            }
+        }
+    }
+    Ok(steps)
+}
+
+/// plan of `build_crate_step`
+pub fn plan_build_crate_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    mode: BuildMode,
+    config: &Config,
+    clean: bool,
+) -> anyhow::Result<PlanStep> {
+    let clean = match mode {
+        BuildMode::Release => release_clean(krate, toolchain, profile, config, clean)?,
+        BuildMode::Check | BuildMode::Debug | BuildMode::Incremental => clean,
+    };
+    let mut step = build_step(krate, toolchain, profile, mode, config, clean)?;
+    // all modes share the target folder, count it for the first one
+    step.disk_bytes = if krate.build_modes.first() == Some(&mode) {
+        crate::plan::estimate_new_folder(&utils::target_folder(krate, profile, toolchain, config))?
+    } else {
+        Some(0)
+    };
+    if mode == BuildMode::Release && !clean && is_up_to_date(krate, toolchain, profile, config)? {
+        step.cached = Some("artifact is up to date".to_string());
+    }
+    Ok(step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(folder: &std::path::Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=ctm",
+                "-c",
+                "user.email=ctm@localhost",
+                "-C",
+            ])
+            .arg(folder)
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    fn new_repo(name: &str) -> std::path::PathBuf {
+        let folder = std::env::temp_dir().join(format!("ctm-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        git(&folder, &["init", "-q"]);
+        folder
+    }
+
+    #[test]
+    fn checkout_commit_changes_with_every_edit() {
+        let folder = new_repo("edits");
+        std::fs::write(folder.join("main.rs"), "21").unwrap();
+        git(&folder, &["add", "."]);
+        git(&folder, &["commit", "-q", "-m", "init"]);
+        let clean = checkout_commit(&folder).unwrap().unwrap();
+
+        std::fs::write(folder.join("main.rs"), "22").unwrap();
+        let first = checkout_commit(&folder).unwrap().unwrap();
+        std::fs::write(folder.join("main.rs"), "23").unwrap();
+        let second = checkout_commit(&folder).unwrap().unwrap();
+        std::fs::write(folder.join("new.rs"), "1").unwrap();
+        let untracked = checkout_commit(&folder).unwrap().unwrap();
+
+        assert!(first.starts_with(&format!("{clean}-dirty-")));
+        assert_ne!(first, second);
+        assert_ne!(second, untracked);
+        assert_eq!(untracked, checkout_commit(&folder).unwrap().unwrap());
+
+        // build output in target doesn't make it dirty
+        std::fs::remove_file(folder.join("new.rs")).unwrap();
+        std::fs::write(folder.join("main.rs"), "21").unwrap();
+        std::fs::create_dir(folder.join("target")).unwrap();
+        std::fs::write(folder.join("target/out"), "").unwrap();
+        assert_eq!(checkout_commit(&folder).unwrap().unwrap(), clean);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn checkout_commit_without_commit() {
+        let folder = new_repo("empty");
+        std::fs::write(folder.join("main.rs"), "21").unwrap();
+        assert_eq!(checkout_commit(&folder).unwrap(), None);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
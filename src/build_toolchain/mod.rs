@@ -1,3 +1,7 @@
+use crate::plan::{quote, PlanStep};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
 pub struct ToolChainOpts {
     pub name: String,
     pub rust_rev: String,
@@ -9,39 +13,84 @@ pub struct ToolChainOpts {
     pub force: bool,
 }
 
-pub fn build_toolchain(
-    rust_repo: &std::path::Path,
-    toolchain: &ToolChainOpts,
-) -> anyhow::Result<()> {
-    log::info!("build toolchain for {}", toolchain.name);
+impl ToolChainOpts {
+    pub fn from_config(
+        toolchain: &crate::config::ToolchainConfig,
+        global: &crate::config::GlobalConfig,
+        force: bool,
+    ) -> Self {
+        ToolChainOpts {
+            name: toolchain.name.clone(),
+            rust_rev: toolchain
+                .rust_rev
+                .clone()
+                .unwrap_or_else(|| global.rust_rev.clone()),
+            patches: toolchain.patches.clone(),
+            patch_folder: global.patches_root(),
+            toolchains_root: global.toolchains_root(),
+            force,
+        }
+    }
 
-    let base_rev = &toolchain.rust_rev;
-    let name = toolchain.name.clone();
-    let toolchain_folder = toolchain.toolchains_root.join(&name);
-
-    if !toolchain_folder.join("bin/rustc").exists() || toolchain.force {
-        // reset rust repo to base rev
-        log::debug!("reset repo {:?} to {}", rust_repo, base_rev);
-        cmd_lib::run_cmd! (
-            cd $rust_repo;
-            git reset --hard $base_rev;
-        )?;
-
-        for patch_name in toolchain.patches.iter() {
-            let patch_file = toolchain.patch_folder.join(patch_name);
-            log::debug!("apply patch {:?}", patch_file);
-            cmd_lib::run_cmd! (
-                cd $rust_repo;
-                git apply $patch_file;
-            )?;
+    pub fn toolchain_folder(&self) -> std::path::PathBuf {
+        self.toolchains_root.join(&self.name)
+    }
+
+    /// stamp written into toolchain folder after a successful build
+    pub fn stamp_path(&self) -> std::path::PathBuf {
+        self.toolchain_folder().join("ctm-stamp.json")
+    }
+
+    /// the stamp a toolchain built with current options would have, with rust rev resolved
+    /// to a commit of rust repo, as a branch or tag may move
+    pub fn expected_stamp(&self, rust_repo: &std::path::Path) -> anyhow::Result<ToolchainStamp> {
+        Ok(ToolchainStamp {
+            rust_rev: resolve_rev(rust_repo, &self.rust_rev)?,
+            patches: self.patch_hashes()?,
+        })
+    }
+
+    /// (patch file name, sha256) in apply order
+    pub fn patch_hashes(&self) -> anyhow::Result<Vec<(String, String)>> {
+        self.patches
+            .iter()
+            .map(|p| {
+                Ok((
+                    p.clone(),
+                    crate::metadata::sha256(&self.patch_folder.join(p))?,
+                ))
+            })
+            .collect()
+    }
+
+    /// why toolchain needs to be built, `None` if it is up to date
+    pub fn staleness(&self, rust_repo: &std::path::Path) -> anyhow::Result<Option<String>> {
+        if !self.toolchain_folder().join("bin/rustc").exists() {
+            return Ok(Some("not built".to_string()));
+        }
+        // the stamp is only written once a build succeeds
+        let stamp = match self.stamp() {
+            Some(stamp) => stamp,
+            None => return Ok(Some("no stamp of a successful build".to_string())),
+        };
+        if !rust_repo.exists() {
+            return Ok(Some("rust repo to compare with is missing".to_string()));
         }
+        let expected = self.expected_stamp(rust_repo)?;
+        let reason = if stamp.rust_rev != expected.rust_rev {
+            "rust rev changed"
+        } else if stamp.patches != expected.patches {
+            "patches changed"
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(reason.to_string()))
+    }
 
-        let config_toml = rust_repo.join("config.toml");
-        // setup config.toml
-        std::fs::write(
-            config_toml,
-            format!(
-                r#"
+    /// config.toml of rust repo to build the toolchain with
+    fn config_toml(&self) -> String {
+        format!(
+            r#"
 # Includes one of the default files in src/bootstrap/defaults
 profile = "user"
 changelog-seen = 2
@@ -58,25 +107,157 @@ tools = ["cargo", "src"]
 [rust]
 description = "{toolchain_name}+{base_rev}"
 "#,
-                prefix = toolchain_folder.to_str().unwrap().to_string(),
-                toolchain_name = toolchain.name,
-                base_rev = base_rev,
-            ),
-        )?;
-
-        cmd_lib::run_cmd!(
-            cd $rust_repo;
-            python x.py install
-        )?;
-    } else {
-        // todo: maybe add more checks? Now we strongly depends on
-        // name uniqueness
-        log::info!("toolchain already build");
+            prefix = self.toolchain_folder().to_str().unwrap(),
+            toolchain_name = self.name,
+            base_rev = self.rust_rev,
+        )
+    }
+
+    /// stamp of the built toolchain, `None` if no build of it succeeded
+    pub fn stamp(&self) -> Option<ToolchainStamp> {
+        let content = std::fs::read_to_string(self.stamp_path()).ok()?;
+        serde_json::from_str(&content).ok()
     }
+}
+
+/// commit `rev` points to in rust repo
+fn resolve_rev(rust_repo: &std::path::Path, rev: &str) -> anyhow::Result<String> {
+    let commit = format!("{rev}^{{commit}}");
+    cmd_lib::run_fun!(git -C $rust_repo rev-parse --verify -q $commit)
+        .map_err(|e| anyhow::anyhow!("can't resolve rust rev {rev} in {rust_repo:?}: {e}"))
+}
 
-    cmd_lib::run_cmd!(
-        rustup toolchain link $name $toolchain_folder;
-    )?;
+/// what a toolchain is built from, to tell whether it is stale
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolchainStamp {
+    /// commit toolchain is built at
+    pub rust_rev: String,
+
+    /// (patch file name, sha256) in apply order
+    pub patches: Vec<(String, String)>,
+}
+
+pub fn build_toolchain(
+    rust_repo: &std::path::Path,
+    toolchain: &ToolChainOpts,
+) -> anyhow::Result<()> {
+    log::info!("build toolchain for {}", toolchain.name);
+
+    let (build, link) = toolchain_steps(rust_repo, toolchain)?;
+    match build.cached.as_ref() {
+        Some(reason) => log::info!("toolchain {reason}"),
+        None => {
+            // a failed install may leave bin/rustc behind, without stamp it is stale
+            if toolchain.stamp_path().exists() {
+                std::fs::remove_file(toolchain.stamp_path())?;
+            }
+            build.execute()?;
+            let stamp = toolchain.expected_stamp(rust_repo)?;
+            std::fs::write(
+                toolchain.stamp_path(),
+                serde_json::to_string_pretty(&stamp)?,
+            )?;
+        }
+    }
+    link.execute()?;
 
     Ok(())
 }
+
+/// the steps building toolchain in rust repo and linking it with rustup, building is
+/// cached if the toolchain is up to date with its stamp and not forced
+fn toolchain_steps(
+    rust_repo: &std::path::Path,
+    toolchain: &ToolChainOpts,
+) -> anyhow::Result<(PlanStep, PlanStep)> {
+    let toolchain_folder = toolchain.toolchain_folder();
+    let mut build = PlanStep::new(format!("build-toolchain {}", toolchain.name));
+    build.cwd = Some(rust_repo.to_path_buf());
+    match toolchain.staleness(rust_repo)? {
+        _ if toolchain.force => {}
+        Some(reason) => build.notes.push(format!("out of date: {reason}")),
+        None => build.cached = Some("up to date".to_string()),
+    }
+
+    // reset rust repo to base rev, ignored files like build folder are kept. Untracked
+    // files are the user's, except ones left by an interrupted build which the patches
+    // would add again, only those are removed
+    build
+        .cmds
+        .push(format!("git reset --hard {}", quote(&toolchain.rust_rev)));
+    let mut created = vec![];
+    for patch_name in toolchain.patches.iter() {
+        created.extend(created_files(&toolchain.patch_folder.join(patch_name))?);
+    }
+    if !created.is_empty() {
+        build.cmds.push(format!(
+            "git clean -f -- {}",
+            created.iter().map(|f| quote(f)).join(" ")
+        ));
+    }
+    for patch_name in toolchain.patches.iter() {
+        let patch_file = quote(toolchain.patch_folder.join(patch_name).to_str().unwrap());
+        build.cmds.push(format!("git apply {patch_file}"));
+        // files added by patches are untracked and survive the reset of the next build
+        build
+            .cleanup
+            .insert(0, format!("git apply -R {patch_file}"));
+    }
+    build.cmds.push(format!(
+        "cat > config.toml <<'EOF'{}EOF",
+        toolchain.config_toml()
+    ));
+    build.cmds.push("python x.py install".to_string());
+    build.notes.push(format!(
+        "{:?} is written after install",
+        toolchain.stamp_path()
+    ));
+
+    let mut link = PlanStep::new(format!("link toolchain {}", toolchain.name));
+    link.cmds.push(format!(
+        "rustup toolchain link {} {}",
+        quote(&toolchain.name),
+        quote(toolchain_folder.to_str().unwrap())
+    ));
+    link.disk_bytes = Some(0);
+    Ok((build, link))
+}
+
+/// files a patch creates, from its `--- /dev/null` hunks
+fn created_files(patch_file: &std::path::Path) -> anyhow::Result<Vec<String>> {
+    let content = std::fs::read_to_string(patch_file)
+        .map_err(|e| anyhow::anyhow!("can't read patch {patch_file:?}: {e}"))?;
+    Ok(content
+        .lines()
+        .tuple_windows()
+        .filter(|(old, _)| *old == "--- /dev/null")
+        .filter_map(|(_, new)| new.strip_prefix("+++ b/"))
+        .map(|path| path.to_string())
+        .collect())
+}
+
+/// plan of `build_toolchain` for each toolchain in order
+pub fn plan_build_toolchain(
+    rust_repo: &std::path::Path,
+    toolchains: &[ToolChainOpts],
+) -> anyhow::Result<Vec<PlanStep>> {
+    // build folder of rust repo is created by the first toolchain built
+    let mut build_folder_exists = rust_repo.join("build").exists();
+
+    let mut steps = vec![];
+    for toolchain in toolchains.iter() {
+        let (mut build, link) = toolchain_steps(rust_repo, toolchain)?;
+        if build.cached.is_none() {
+            let mut disk_bytes = crate::plan::estimate_new_folder(&toolchain.toolchain_folder())?
+                .unwrap_or(crate::plan::TOOLCHAIN_ESTIMATE);
+            if !build_folder_exists {
+                disk_bytes += crate::plan::RUST_BUILD_ESTIMATE;
+                build_folder_exists = true;
+            }
+            build.disk_bytes = Some(disk_bytes);
+        }
+        steps.push(build);
+        steps.push(link);
+    }
+    Ok(steps)
+}
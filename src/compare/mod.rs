@@ -0,0 +1,80 @@
+/// compare two saved `build-crate` or `run` results, cell by cell
+use crate::report::row_key;
+use serde_json::{Map, Value};
+
+type Row = Map<String, Value>;
+
+#[derive(Debug)]
+pub struct MetricDelta {
+    pub krate: String,
+    pub toolchain: String,
+    pub profile: String,
+
+    /// run cmd or build mode
+    pub name: String,
+
+    /// compared column, e.g: `binary_size`
+    pub metric: String,
+
+    pub old: f64,
+    pub new: f64,
+}
+
+impl MetricDelta {
+    /// new / old
+    pub fn ratio(&self) -> f64 {
+        self.new / self.old
+    }
+}
+
+/// join rows by (krate, cmd or mode, toolchain, profile), and pair up binary size, build
+/// duration and every duration statistic of joined cells
+pub fn compare(old: &[Row], new: &[Row]) -> Vec<MetricDelta> {
+    let mut deltas = vec![];
+
+    for row in new.iter() {
+        let (krate, name, toolchain, profile) = match row_key(row) {
+            Some(key) => key,
+            None => continue,
+        };
+        let old_row = match old.iter().find(|r| row_key(r) == row_key(row)) {
+            Some(old_row) => old_row,
+            None => {
+                log::warn!("{krate} {name} {toolchain}_{profile} is only in new results");
+                continue;
+            }
+        };
+
+        for (metric, value) in row.iter() {
+            let compared = metric == "binary_size"
+                || metric == "build_duration_ms"
+                || metric.starts_with("duration_ns_");
+            if !compared {
+                continue;
+            }
+            let (old, new) = match (old_row.get(metric).and_then(|v| v.as_f64()), value.as_f64()) {
+                (Some(old), Some(new)) if old > 0.0 && new > 0.0 => (old, new),
+                _ => continue,
+            };
+            deltas.push(MetricDelta {
+                krate: krate.to_string(),
+                toolchain: toolchain.to_string(),
+                profile: profile.to_string(),
+                name: name.to_string(),
+                metric: metric.clone(),
+                old,
+                new,
+            });
+        }
+    }
+
+    for row in old.iter() {
+        if let Some((krate, name, toolchain, profile)) = row_key(row) {
+            if !new.iter().any(|r| row_key(r) == row_key(row)) {
+                log::warn!("{krate} {name} {toolchain}_{profile} is only in old results");
+            }
+        }
+    }
+
+    deltas
+}
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Debug)]
 pub struct GlobalConfig {
@@ -43,7 +43,8 @@ impl GlobalConfig {
         self.to_absolute_path(self.build_root.as_str())
     }
 
-    fn to_absolute_path(&self, path: &str) -> std::path::PathBuf {
+    /// resolve path relative to project root
+    pub fn to_absolute_path(&self, path: &str) -> std::path::PathBuf {
         let path = std::path::PathBuf::from(path);
         if path.is_absolute() {
             path
@@ -94,14 +95,130 @@ pub struct CrateOpt {
     #[serde(default)]
     pub build_cmd: Option<String>,
 
+    /// if provided, override default test cmd
+    #[serde(default)]
+    pub test_cmd: Option<String>,
+
     /// output artifact path relative to target folder
     pub output_path: String,
 
+    /// build modes to benchmark for each toolchain + profile, default is release only
+    #[serde(default = "default_build_modes")]
+    pub build_modes: Vec<BuildMode>,
+
+    /// how to change the source for incremental build mode
+    #[serde(default)]
+    pub incremental: IncrementalOpt,
+
+    /// how to strip the output artifact, overrides profile's setting
+    #[serde(default)]
+    pub strip: Option<StripMode>,
+
+    /// rust crate names to collect self profile data for, default is crate name
+    /// with `-` replaced by `_`
+    #[serde(default)]
+    pub self_profile_crates: Vec<String>,
+
     /// runs, all runs defined for this crate, they can be executed
     /// for each toolchain + profile permutation, and will collect
     /// run duration etc to indicate toolchain + profile perf difference
     #[serde(default)]
     pub runs: Vec<Run>,
+
+    /// regression thresholds checked by `ctm gate`, runs can override them
+    #[serde(default)]
+    pub gates: GateOpt,
+}
+
+impl CrateOpt {
+    /// cmd for release build, `build_cmd` if provided
+    pub fn release_build_cmd(&self) -> String {
+        self.build_cmd
+            .as_ref()
+            .cloned()
+            .unwrap_or_else(|| "cargo build --release".to_string())
+    }
+
+    /// cmd building in `mode`, derived from `release_build_cmd` so features, `--bin` and
+    /// the like are kept: `--release` is dropped, and `build` becomes `check` for check mode
+    pub fn mode_build_cmd(&self, mode: BuildMode) -> anyhow::Result<String> {
+        let release_cmd = self.release_build_cmd();
+        if mode == BuildMode::Release {
+            return Ok(release_cmd);
+        }
+
+        // split on single spaces so joining keeps the rest of the cmd as is
+        let mut words = release_cmd.split(' ').collect::<Vec<_>>();
+        let build = words
+            .iter()
+            .position(|w| *w == "cargo")
+            .and_then(|cargo| {
+                words[cargo..]
+                    .iter()
+                    .position(|w| *w == "build")
+                    .map(|i| cargo + i)
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "can't derive {mode:?} build of crate {} from build_cmd `{release_cmd}`, it is not a `cargo build`",
+                    self.name
+                )
+            })?;
+        if mode == BuildMode::Check {
+            words[build] = "check";
+        }
+        let words = words
+            .iter()
+            .enumerate()
+            .filter(|(i, w)| *i < build || !matches!(**w, "--release" | "-r"))
+            .map(|(_, w)| *w)
+            .collect::<Vec<_>>();
+        Ok(words.join(" "))
+    }
+}
+
+fn default_build_modes() -> Vec<BuildMode> {
+    vec![BuildMode::Release]
+}
+
+/// how the crate is built, each mode is timed and reported separately
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildMode {
+    /// `build_cmd` as `cargo check` without `--release`, after cleaning dev profile
+    Check,
+    /// `build_cmd` without `--release`, after cleaning dev profile
+    Debug,
+    /// `cargo build --release`, or `build_cmd` if provided
+    Release,
+    /// debug build after cleaning dev profile, then change one source file and time the
+    /// rebuild
+    Incremental,
+}
+
+/// source change applied before the timed rebuild of incremental build mode
+#[derive(Deserialize, Debug, Default)]
+pub struct IncrementalOpt {
+    /// file to touch, relative to crate root. Default is src/main.rs or src/lib.rs
+    #[serde(default)]
+    pub touch: Option<String>,
+
+    /// patch file to apply, relative to project root. It is reverted after the rebuild
+    #[serde(default)]
+    pub patch: Option<String>,
+}
+
+/// how output artifact is stripped, an unstripped copy is always kept alongside
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StripMode {
+    /// keep the artifact as is
+    None,
+    /// `strip --strip-debug`
+    Debuginfo,
+    /// `strip`
+    #[default]
+    All,
 }
 
 #[derive(Deserialize, Debug)]
@@ -109,6 +226,10 @@ pub struct Profile {
     /// the name for profile, should be unique
     pub name: String,
 
+    /// how to strip the output artifact, default is all
+    #[serde(default)]
+    pub strip: Option<StripMode>,
+
     /// the config.toml content
     pub environ: std::collections::HashMap<String, String>,
 }
@@ -118,11 +239,160 @@ pub struct Run {
     /// run name, can be used as param to just run this one
     pub name: String,
 
-    /// how many times to run
+    /// how many times to run, ignored if `adaptive` provided
+    #[serde(default)]
     pub count: u64,
 
+    /// if provided, the number of samples is decided by measurement noise
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveOpt>,
+
     /// args passed to program
+    #[serde(default)]
     pub args: Vec<String>,
+
+    /// if provided, run this shell cmd (pipelines allowed) instead of program with args.
+    /// Artifact folder is prepended to PATH, so the program can be invoked by its name
+    #[serde(default)]
+    pub shell: Option<String>,
+
+    /// extra environment variables passed to program
+    #[serde(default)]
+    pub env: std::collections::HashMap<String, String>,
+
+    /// working directory of program, relative to project root. `{crate}` is replaced
+    /// with crate checkout folder. Default is current folder
+    #[serde(default)]
+    pub cwd: Option<String>,
+
+    /// file piped into program's stdin, resolved the same way as `cwd`
+    #[serde(default)]
+    pub stdin: Option<String>,
+
+    /// kill the program if it runs longer than this, the sample is recorded as timed out
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// shell cmds executed once before all samples of each toolchain + profile
+    #[serde(default)]
+    pub setup: Vec<String>,
+
+    /// shell cmds executed once after all samples of each toolchain + profile
+    #[serde(default)]
+    pub teardown: Vec<String>,
+
+    /// shell cmds executed before every sample, not counted into duration
+    #[serde(default)]
+    pub prepare: Vec<String>,
+
+    /// regression thresholds of this run, override crate's
+    #[serde(default)]
+    pub gates: GateOpt,
+}
+
+/// max allowed regression in percent against the reference result, unset means not gated
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct GateOpt {
+    /// run duration slowdown
+    #[serde(default)]
+    pub max_slowdown_pct: Option<f64>,
+
+    /// binary size growth
+    #[serde(default)]
+    pub max_size_growth_pct: Option<f64>,
+
+    /// build duration growth
+    #[serde(default)]
+    pub max_build_time_growth_pct: Option<f64>,
+}
+
+impl GateOpt {
+    /// thresholds set in `self`, otherwise the ones in `fallback`
+    pub fn or(&self, fallback: &GateOpt) -> GateOpt {
+        GateOpt {
+            max_slowdown_pct: self.max_slowdown_pct.or(fallback.max_slowdown_pct),
+            max_size_growth_pct: self.max_size_growth_pct.or(fallback.max_size_growth_pct),
+            max_build_time_growth_pct: self
+                .max_build_time_growth_pct
+                .or(fallback.max_build_time_growth_pct),
+        }
+    }
+}
+
+/// adaptive sampling, keep sampling until the mean duration is stable
+#[derive(Deserialize, Debug)]
+pub struct AdaptiveOpt {
+    /// at least run this many times
+    #[serde(default = "default_adaptive_min_count")]
+    pub min_count: u64,
+
+    /// at most run this many times
+    #[serde(default = "default_adaptive_max_count")]
+    pub max_count: u64,
+
+    /// stop once the 95% confidence interval half width of mean duration, relative
+    /// to the mean, is below this
+    #[serde(default = "default_adaptive_target_rel_ci")]
+    pub target_rel_ci: f64,
+
+    /// stop once total duration of samples exceeds this
+    #[serde(default)]
+    pub max_time_ms: Option<u64>,
+}
+
+fn default_adaptive_min_count() -> u64 {
+    5
+}
+
+fn default_adaptive_max_count() -> u64 {
+    100
+}
+
+fn default_adaptive_target_rel_ci() -> f64 {
+    0.02
+}
+
+/// how the measured program of runs is executed, to reduce noise
+#[derive(Deserialize, Debug)]
+pub struct BenchOpt {
+    /// pin the measured program to these cpus, in `taskset -c` format, e.g: `2-3`
+    #[serde(default)]
+    pub cpus: Option<String>,
+
+    /// nice value of the measured program, negative value requires privilege
+    #[serde(default)]
+    pub nice: Option<i32>,
+
+    /// io scheduling class of the measured program, 1: realtime, 2: best-effort, 3: idle
+    #[serde(default)]
+    pub ionice_class: Option<u32>,
+
+    /// io priority within class, 0 (highest) to 7
+    #[serde(default)]
+    pub ionice_level: Option<u32>,
+
+    /// warn if 1 minute load average is higher than this before sampling, number of cpus
+    /// by default
+    #[serde(default = "default_bench_max_load")]
+    pub max_load: f64,
+}
+
+impl Default for BenchOpt {
+    fn default() -> Self {
+        Self {
+            cpus: None,
+            nice: None,
+            ionice_class: None,
+            ionice_level: None,
+            max_load: default_bench_max_load(),
+        }
+    }
+}
+
+fn default_bench_max_load() -> f64 {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1) as f64
 }
 
 #[derive(Deserialize, Debug)]
@@ -138,14 +408,70 @@ pub struct Config {
 
     /// crates
     pub crates: Vec<CrateOpt>,
+
+    /// benchmark environment for runs
+    #[serde(default)]
+    pub bench: BenchOpt,
+
+    /// the file config loaded from
+    #[serde(skip)]
+    pub config_file: std::path::PathBuf,
+}
+
+impl Config {
+    /// find profile by name
+    pub fn profile(&self, name: &str) -> anyhow::Result<&Profile> {
+        self.profiles
+            .iter()
+            .find(|p| p.name.eq(name))
+            .ok_or_else(|| anyhow::anyhow!("profile {name} not defined"))
+    }
+
+    /// find toolchain by name
+    pub fn toolchain(&self, name: &str) -> anyhow::Result<&ToolchainConfig> {
+        self.toolchains
+            .iter()
+            .find(|t| t.name.eq(name))
+            .ok_or_else(|| anyhow::anyhow!("toolchain {name} not defined"))
+    }
+
+    /// find crate by name
+    pub fn krate(&self, name: &str) -> anyhow::Result<&CrateOpt> {
+        self.crates
+            .iter()
+            .find(|k| k.name.eq(name))
+            .ok_or_else(|| anyhow::anyhow!("Not able to find crate"))
+    }
+
+    /// the baseline toolchain, which is the first one defined in config
+    pub fn baseline_toolchain(&self) -> anyhow::Result<&ToolchainConfig> {
+        self.toolchains
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("no toolchain defined"))
+    }
+}
+
+/// load config of cmds which only need it for report metadata: the given file, otherwise
+/// config.toml if it exists
+pub fn load_optional(file: Option<&str>) -> anyhow::Result<Option<Config>> {
+    match file {
+        Some(file) => Ok(Some(load_from_file(file)?)),
+        None if std::path::Path::new("config.toml").exists() => {
+            Ok(Some(load_from_file("config.toml")?))
+        }
+        None => Ok(None),
+    }
 }
 
 /// load crate from config file
 pub fn load_from_file(file: &str) -> anyhow::Result<Config> {
-    let file = std::path::PathBuf::from(file).canonicalize().unwrap();
+    let file = std::path::PathBuf::from(file)
+        .canonicalize()
+        .map_err(|e| anyhow::anyhow!("can't open config {file}: {e}"))?;
     let parent = file.parent().unwrap();
     let content = std::fs::read_to_string(&file)?;
     let mut config: Config = toml::from_str(content.as_str())?;
+    config.config_file = file.clone();
     if config.global.project_root.is_empty() {
         config.global.project_root = parent.to_str().unwrap().to_string();
     }
@@ -0,0 +1,68 @@
+/// `ctm exec` and `ctm shell`: run a cmd or an interactive shell in crate checkout, with
+/// the environment `build_crate_step` builds a (toolchain, profile) cell with, to debug
+/// a failing cell by hand
+use crate::config::{Config, CrateOpt, Profile, ToolchainConfig};
+use crate::plan::{quote, PlanStep};
+use crate::utils;
+use itertools::Itertools;
+
+/// run `cmd` in crate checkout with the cell's build environ, returns its exit status
+pub fn exec(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+    cmd: &[String],
+) -> anyhow::Result<std::process::ExitStatus> {
+    if cmd.is_empty() {
+        anyhow::bail!("no cmd to exec");
+    }
+    if !toolchain.profiles.contains(&profile.name) {
+        log::warn!(
+            "profile {} is not built with toolchain {}",
+            profile.name,
+            toolchain.name
+        );
+    }
+
+    let steps = plan_exec(krate, toolchain, profile, config, cmd)?;
+    let (step, checkout) = steps.split_last().unwrap();
+    for checkout in checkout.iter() {
+        checkout.execute()?;
+    }
+    log::info!("{}: {}", step.title, step.cmds[0]);
+    Ok(step.command(&step.cmds[0]).status()?)
+}
+
+/// the cmd `ctm shell` spawns, user's `$SHELL` or bash
+pub fn shell_cmd() -> Vec<String> {
+    vec![std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string())]
+}
+
+/// plan of `exec`, the exec step is the last one
+pub fn plan_exec(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+    cmd: &[String],
+) -> anyhow::Result<Vec<PlanStep>> {
+    let mut step = PlanStep::new(format!(
+        "exec in {} {}_{}",
+        krate.name, toolchain.name, profile.name
+    ));
+    step.cwd = Some(utils::crate_folder(krate, config));
+    step.env = utils::build_environ(krate, profile, toolchain, config)
+        .into_iter()
+        .collect();
+    step.cmds.push(cmd.iter().map(|arg| quote(arg)).join(" "));
+    step.disk_bytes = Some(0);
+
+    // checkout is left as is if it exists, unlike build-crate it is not synced
+    let mut steps = vec![];
+    if !utils::crate_folder(krate, config).exists() {
+        steps.push(utils::plan_checkout(krate, config)?);
+    }
+    steps.push(step);
+    Ok(steps)
+}
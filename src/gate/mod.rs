@@ -0,0 +1,136 @@
+/// regression gates: compare saved `build-crate` and `run` results with a reference, either
+/// the baseline toolchain's cell in the same results or a previous result, and check the
+/// changes against thresholds in config
+use crate::config::{Config, GateOpt};
+use crate::report::row_key;
+use serde_json::{Map, Value};
+
+type Row = Map<String, Value>;
+
+/// what results are compared with
+pub enum Reference<'a> {
+    /// the cell of this toolchain with the same crate, cmd/mode and profile
+    Baseline(&'a str),
+    /// the cell with the same crate, cmd/mode, toolchain and profile in previous results
+    Previous(&'a [Row]),
+}
+
+#[derive(Debug)]
+pub struct GateResult {
+    pub krate: String,
+    pub toolchain: String,
+    pub profile: String,
+
+    /// run cmd or build mode
+    pub name: String,
+
+    /// column checked, e.g: `duration_ns_p50`
+    pub metric: String,
+
+    pub value: f64,
+    pub reference: f64,
+
+    /// change relative to reference in percent, positive means regression
+    pub change_pct: f64,
+
+    /// threshold from config
+    pub max_pct: f64,
+
+    pub pass: bool,
+}
+
+/// check each row with thresholds configured, rows without reference are skipped
+pub fn check_gates(
+    rows: &[Row],
+    reference: &Reference,
+    config: &Config,
+) -> anyhow::Result<Vec<GateResult>> {
+    let mut results = vec![];
+
+    for row in rows.iter() {
+        let (krate, name, toolchain, profile) = match row_key(row) {
+            Some(key) => key,
+            None => continue,
+        };
+        let krate_opt = match config.krate(krate) {
+            Ok(krate_opt) => krate_opt,
+            Err(_) => {
+                log::warn!("crate {krate} not in config, skip gates");
+                continue;
+            }
+        };
+
+        let is_run = row.contains_key("cmd");
+        let gates = if is_run {
+            match krate_opt.runs.iter().find(|r| r.name == name) {
+                Some(run) => run.gates.or(&krate_opt.gates),
+                None => krate_opt.gates.clone(),
+            }
+        } else {
+            krate_opt.gates.clone()
+        };
+
+        let other = match reference {
+            Reference::Baseline(baseline) if toolchain == *baseline => continue,
+            Reference::Baseline(baseline) => rows
+                .iter()
+                .find(|r| row_key(r) == Some((krate, name, baseline, profile))),
+            Reference::Previous(previous) => previous.iter().find(|r| row_key(r) == row_key(row)),
+        };
+        let other = match other {
+            Some(other) => other,
+            None => {
+                log::warn!("no reference for {krate} {name} {toolchain}_{profile}, skip gates");
+                continue;
+            }
+        };
+
+        for (metric, max_pct) in metrics(row, is_run, &gates) {
+            let (value, reference) = match (number(row, metric), number(other, metric)) {
+                (Some(value), Some(reference)) if reference > 0.0 => (value, reference),
+                _ => continue,
+            };
+            let change_pct = (value - reference) / reference * 100.0;
+            results.push(GateResult {
+                krate: krate.to_string(),
+                toolchain: toolchain.to_string(),
+                profile: profile.to_string(),
+                name: name.to_string(),
+                metric: metric.to_string(),
+                value,
+                reference,
+                change_pct,
+                max_pct,
+                pass: change_pct <= max_pct,
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+/// gated metrics of row with their thresholds
+fn metrics(row: &Row, is_run: bool, gates: &GateOpt) -> Vec<(&'static str, f64)> {
+    let mut metrics = vec![];
+    if is_run {
+        // median is less sensitive to outliers, if reported
+        let duration = if row.contains_key("duration_ns_p50") {
+            "duration_ns_p50"
+        } else {
+            "duration_ns_mean"
+        };
+        metrics.extend(gates.max_slowdown_pct.map(|pct| (duration, pct)));
+    } else {
+        metrics.extend(
+            gates
+                .max_build_time_growth_pct
+                .map(|pct| ("build_duration_ms", pct)),
+        );
+    }
+    metrics.extend(gates.max_size_growth_pct.map(|pct| ("binary_size", pct)));
+    metrics
+}
+
+fn number(row: &Row, column: &str) -> Option<f64> {
+    row.get(column).and_then(|v| v.as_f64())
+}
@@ -10,3 +10,17 @@ pub fn init_project(folder: std::path::PathBuf) -> anyhow::Result<std::path::Pat
 
     Ok(folder)
 }
+
+/// plan of `init_project`
+pub fn plan_init_project(folder: std::path::PathBuf) -> crate::plan::PlanStep {
+    let config_path = folder.join("config.toml");
+    let mut step = crate::plan::PlanStep::new(format!("init project at {folder:?}"));
+    step.notes
+        .push(format!("write example config to {config_path:?}"));
+    if config_path.exists() {
+        step.notes
+            .push("fails: config file already exists".to_string());
+    }
+    step.disk_bytes = Some(include_str!("init_config.toml").len() as u64);
+    step
+}
@@ -7,6 +7,56 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+
+    #[clap(
+        long = "dry-run",
+        global = true,
+        help = "print cells, cmds, env vars, cached steps and estimated disk usage, execute nothing"
+    )]
+    dry_run: bool,
+}
+
+/// glob filters of (crate, toolchain, profile, run) cells, each can be repeated
+#[derive(Debug, clap::Args)]
+struct CellFilterArgs {
+    #[clap(long = "crate", help = "only crates matching this glob")]
+    crates: Vec<String>,
+
+    #[clap(long = "toolchain", help = "only toolchains matching this glob")]
+    toolchains: Vec<String>,
+
+    #[clap(long = "profile", help = "only profiles matching this glob")]
+    profiles: Vec<String>,
+
+    #[clap(
+        long = "run",
+        help = "only runs matching this glob, build-crate builds crates having such runs"
+    )]
+    runs: Vec<String>,
+}
+
+impl From<CellFilterArgs> for utils::CellFilter {
+    fn from(args: CellFilterArgs) -> Self {
+        utils::CellFilter {
+            crates: args.crates,
+            toolchains: args.toolchains,
+            profiles: args.profiles,
+            runs: args.runs,
+        }
+    }
+}
+
+/// one (crate, toolchain, profile) cell
+#[derive(Debug, clap::Args)]
+struct CellArgs {
+    #[clap(long = "crate")]
+    krate: String,
+
+    #[clap(long = "toolchain")]
+    toolchain: String,
+
+    #[clap(long = "profile")]
+    profile: String,
 }
 
 #[derive(Debug, Subcommand)]
@@ -35,50 +85,495 @@ enum Commands {
         name: Option<String>,
     },
 
-    #[clap(arg_required_else_help = true)]
+    /// build crates for each toolchain and profile, all of them unless filtered
     BuildCrate {
         #[clap(long = "config", default_value = "config.toml")]
         config: String,
 
-        #[clap(long = "crate", help = "if provided, only build this crate")]
-        krate: Option<String>,
+        #[clap(flatten)]
+        filter: CellFilterArgs,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "json"
+        )]
+        format: report::OutputFormat,
+
+        #[clap(
+            long = "clean",
+            help = "clean release build output before each build, to time clean release builds"
+        )]
+        clean: bool,
+
+        #[clap(
+            long = "self-profile",
+            help = "also build with -Zself-profile, write per query comparison with baseline to this path"
+        )]
+        self_profile: Option<String>,
+    },
+
+    /// run test suite of crate for each toolchain and profile, report tests whose outcome
+    /// differs from the baseline toolchain
+    Test {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(long = "crate")]
+        krate: String,
 
         #[clap(long = "output", help = "output path", default_value = "-")]
         output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "json"
+        )]
+        format: report::OutputFormat,
+
+        #[clap(
+            long = "baseline",
+            help = "baseline toolchain, default is the first toolchain in config"
+        )]
+        baseline: Option<String>,
+
+        #[clap(
+            long = "all",
+            help = "report all tests, not only the ones differ from baseline"
+        )]
+        all: bool,
     },
 
-    /// run the command in number of times, before run, will inject krate target path into PATH for
-    /// each tool chain
-    #[clap(arg_required_else_help = true, trailing_var_arg = true)]
+    /// break down artifact size by section, symbol and crate for each toolchain and profile,
+    /// or diff two of them
+    Size {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(long = "crate")]
+        krate: String,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "json"
+        )]
+        format: report::OutputFormat,
+
+        #[clap(
+            long = "top",
+            help = "how many symbols to report",
+            default_value = "20"
+        )]
+        top: usize,
+
+        #[clap(
+            long = "diff",
+            number_of_values = 2,
+            value_names = &["BASE", "TARGET"],
+            help = "diff two artifacts, each in format toolchain:profile"
+        )]
+        diff: Vec<String>,
+    },
+
+    /// disassemble a function from each toolchain and profile's artifact, and diff it
+    /// against the baseline toolchain
+    Asm {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(long = "crate")]
+        krate: String,
+
+        #[clap(
+            long = "symbol",
+            help = "demangled function path, e.g: dust::dir_walker::walk"
+        )]
+        symbol: String,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "baseline",
+            help = "baseline toolchain, default is the first toolchain in config"
+        )]
+        baseline: Option<String>,
+
+        #[clap(
+            long = "side-by-side",
+            help = "side by side diff instead of unified diff"
+        )]
+        side_by_side: bool,
+    },
+
+    /// build each toolchain and profile twice, report artifacts which are not bit-for-bit
+    /// reproducible
+    Repro {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(long = "crate")]
+        krate: String,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "json"
+        )]
+        format: report::OutputFormat,
+
+        #[clap(
+            long = "remap",
+            help = "build the second time in a copy of crate at another path, with --remap-path-prefix"
+        )]
+        remap: bool,
+    },
+
+    /// run each run of crates in number of times for each toolchain and profile, all of
+    /// them unless filtered. Runs are executed with krate target path injected into PATH
     Run {
         #[clap(long = "config", default_value = "config.toml")]
         config: String,
 
+        #[clap(flatten)]
+        filter: CellFilterArgs,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "json"
+        )]
+        format: report::OutputFormat,
+
+        #[clap(
+            long = "percentile",
+            help = "duration percentiles to report, can be repeated",
+            default_values = &["50", "90"]
+        )]
+        percentiles: Vec<f64>,
+
+        #[clap(
+            long = "rebuild",
+            help = "rebuild missing or stale artifacts instead of failing"
+        )]
+        rebuild: bool,
+
+        #[clap(
+            long = "samples",
+            help = "also write every sample to this path, as csv if it ends with .csv, jsonl otherwise"
+        )]
+        samples: Option<String>,
+    },
+
+    /// check saved build-crate and run results against regression thresholds in config,
+    /// exit with error if any gate fails
+    Gate {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(
+            required = true,
+            help = "result files of build-crate or run, in json or jsonl"
+        )]
+        results: Vec<String>,
+
+        #[clap(
+            long = "previous",
+            help = "compare with these previous result files instead of baseline toolchain"
+        )]
+        previous: Vec<String>,
+
+        #[clap(
+            long = "baseline",
+            help = "baseline toolchain, default is the first toolchain in config"
+        )]
+        baseline: Option<String>,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "table"
+        )]
+        format: report::OutputFormat,
+    },
+
+    /// bootstrap, build toolchains and crates which are out of date, then run, for the
+    /// cells picked by filters
+    All {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(flatten)]
+        filter: CellFilterArgs,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "json"
+        )]
+        format: report::OutputFormat,
+
+        #[clap(
+            long = "percentile",
+            help = "duration percentiles to report, can be repeated",
+            default_values = &["50", "90"]
+        )]
+        percentiles: Vec<f64>,
+    },
+
+    /// run a cmd in crate checkout with the environment the crate is built with for the
+    /// toolchain and profile, e.g: `ctm exec --crate dust --toolchain base --profile minsize -- cargo tree`
+    Exec {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(flatten)]
+        cell: CellArgs,
+
+        #[clap(required = true, last = true, help = "cmd and its args")]
+        cmd: Vec<String>,
+    },
+
+    /// spawn an interactive shell in crate checkout with the environment the crate is built
+    /// with for the toolchain and profile
+    Shell {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(flatten)]
+        cell: CellArgs,
+    },
+
+    /// find the first rust commit between good and bad which makes a run slower (or faster),
+    /// or the binary larger (or smaller). Toolchains are built at first parent commits with
+    /// the patches of a configured toolchain
+    Bisect {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
         #[clap(long = "crate")]
         krate: String,
 
+        #[clap(long = "run", help = "run to sample, required for duration")]
+        run: Option<String>,
+
+        #[clap(long = "good", help = "rust rev without the change")]
+        good: String,
+
+        #[clap(long = "bad", help = "rust rev with the change")]
+        bad: String,
+
+        #[clap(
+            long = "toolchain",
+            help = "toolchain whose patches are applied at each commit, default is the first toolchain in config"
+        )]
+        toolchain: Option<String>,
+
+        #[clap(
+            long = "profile",
+            help = "profile to build with, default is the first profile of toolchain"
+        )]
+        profile: Option<String>,
+
+        #[clap(
+            long = "metric",
+            arg_enum,
+            help = "what is bisected",
+            default_value = "duration"
+        )]
+        metric: bisect::BisectMetric,
+
+        #[clap(
+            long = "threshold",
+            help = "a commit is bad if metric changes more than this percent from good towards bad, default is a t-test at 95% for duration and any change for size"
+        )]
+        threshold: Option<f64>,
+
+        #[clap(
+            long = "state",
+            help = "state file to resume from, default is bisect-<crate>-<run or size>.json in build root"
+        )]
+        state: Option<String>,
+
+        #[clap(
+            long = "keep-toolchains",
+            help = "keep toolchains built for bisecting, they are removed after measuring by default"
+        )]
+        keep_toolchains: bool,
+
         #[clap(long = "output", help = "output path", default_value = "-")]
         output: String,
 
-        #[clap(long = "profile")]
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "table"
+        )]
+        format: report::OutputFormat,
+    },
+
+    /// measure a toolchain built with each prefix of its patches, to find which patch
+    /// changes a crate's run duration or binary size
+    BisectPatches {
+        #[clap(long = "config", default_value = "config.toml")]
+        config: String,
+
+        #[clap(long = "crate")]
+        krate: String,
+
+        #[clap(long = "toolchain", help = "toolchain whose patches are measured")]
+        toolchain: String,
+
+        #[clap(
+            long = "run",
+            help = "run to sample, only binary size is measured without"
+        )]
+        run: Option<String>,
+
+        #[clap(
+            long = "profile",
+            help = "profile to build with, default is the first profile of toolchain"
+        )]
         profile: Option<String>,
+
+        #[clap(
+            long = "state",
+            help = "state file to resume from, default is bisect-patches-<toolchain>-<crate>-<run or size>.json in build root"
+        )]
+        state: Option<String>,
+
+        #[clap(
+            long = "keep-toolchains",
+            help = "keep toolchains built for measuring, they are removed after measuring by default"
+        )]
+        keep_toolchains: bool,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "table"
+        )]
+        format: report::OutputFormat,
+    },
+
+    /// compare two saved build-crate or run result files, cell by cell
+    Compare {
+        #[clap(
+            long = "config",
+            help = "config to describe toolchains and crates in report metadata, config.toml if it exists"
+        )]
+        config: Option<String>,
+
+        #[clap(help = "old result file, in json or jsonl")]
+        old: String,
+
+        #[clap(help = "new result file, in json or jsonl")]
+        new: String,
+
+        #[clap(
+            long = "threshold",
+            help = "changes within this percent are reported as unchanged",
+            default_value = "1.0"
+        )]
+        threshold: f64,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "table"
+        )]
+        format: report::OutputFormat,
+    },
+
+    /// aggregate samples saved by `run --samples` into report rows, without rerunning
+    Aggregate {
+        #[clap(
+            long = "config",
+            help = "config to describe toolchains and crates in report metadata, config.toml if it exists"
+        )]
+        config: Option<String>,
+
+        #[clap(help = "samples file, csv if it ends with .csv, jsonl otherwise")]
+        samples: String,
+
+        #[clap(long = "output", help = "output path", default_value = "-")]
+        output: String,
+
+        #[clap(
+            long = "format",
+            arg_enum,
+            help = "output format",
+            default_value = "json"
+        )]
+        format: report::OutputFormat,
+
+        #[clap(
+            long = "percentile",
+            help = "duration percentiles to report, can be repeated",
+            default_values = &["50", "90"]
+        )]
+        percentiles: Vec<f64>,
     },
 }
 
 fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    // show warnings by default, e.g. noisy benchmark environment
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
     let args = Cli::parse();
+    let dry_run = args.dry_run;
 
     match args.command {
         Commands::Init { folder } => {
             let folder = folder
                 .map(std::path::PathBuf::from)
                 .unwrap_or_else(|| std::env::current_dir().unwrap());
+            if dry_run {
+                return plan::print(&[init::plan_init_project(folder)]);
+            }
             init::init_project(folder)?;
         }
         Commands::Bootstrap { config } => {
             let config = config::load_from_file(config.as_str())?;
+            if dry_run {
+                return plan::print(&bootstrap::plan_bootstrap(&config));
+            }
             bootstrap::bootstrap(&config)?;
         }
 
@@ -89,89 +584,489 @@ fn main() -> anyhow::Result<()> {
         } => {
             let config = config::load_from_file(config.as_str())?;
 
-            let global_option = config.global;
-            let rust_repo = global_option.rust_repo();
-            let toolchains = config.toolchains;
-
-            for toolchain in toolchains.into_iter() {
-                let should_build = name
-                    .as_ref()
-                    .map(|n| n.eq(toolchain.name.as_str()))
-                    .unwrap_or(true);
-
-                if should_build {
-                    let toolchain = build_toolchain::ToolChainOpts {
-                        name: toolchain.name,
-                        rust_rev: toolchain.rust_rev.unwrap_or(global_option.rust_rev.clone()),
-                        patches: toolchain.patches,
-                        patch_folder: global_option.patches_root(),
-                        toolchains_root: global_option.toolchains_root(),
-                        force,
-                    };
-                    build_toolchain::build_toolchain(&rust_repo, &toolchain)?;
-                }
+            let toolchains = config
+                .toolchains
+                .iter()
+                .filter(|toolchain| {
+                    name.as_ref()
+                        .map(|n| n.eq(toolchain.name.as_str()))
+                        .unwrap_or(true)
+                })
+                .map(|toolchain| {
+                    build_toolchain::ToolChainOpts::from_config(toolchain, &config.global, force)
+                })
+                .collect::<Vec<_>>();
+            if dry_run {
+                return plan::print(&build_toolchain::plan_build_toolchain(
+                    &config.global.rust_repo(),
+                    &toolchains,
+                )?);
+            }
+            for toolchain in toolchains.iter() {
+                build_toolchain::build_toolchain(&config.global.rust_repo(), toolchain)?;
             }
         }
 
         Commands::BuildCrate {
             config,
-            krate,
+            filter,
             output,
+            format,
+            clean,
+            self_profile,
         } => {
             let config = config::load_from_file(config.as_str())?;
 
-            let mut crates_iter: Box<dyn Iterator<Item = _>> = Box::new(config.crates.iter());
-            if let Some(name) = krate.as_ref() {
-                crates_iter = Box::new(crates_iter.filter(|k| k.name.eq(name)));
+            let filter = utils::CellFilter::from(filter);
+
+            if dry_run {
+                let mut steps = vec![];
+                for krate in config.crates.iter().filter(|k| filter.krate(k)) {
+                    steps.append(&mut build_crate::plan_build_crate_for_all_profile(
+                        krate, &config, &filter, clean,
+                    )?);
+                    if self_profile.is_some() {
+                        steps.append(&mut self_profile::plan_self_profile_for_all_profile(
+                            krate, &config, &filter,
+                        )?);
+                    }
+                }
+                return plan::print(&steps);
             }
 
             let mut rows = vec![];
-            for krate in crates_iter {
+            let mut query_profiles = vec![];
+            for krate in config.crates.iter().filter(|k| filter.krate(k)) {
                 log::info!("building {}", krate.name);
-                let artifacts = build_crate::build_crate_for_all_profile(krate, &config)?;
+                let artifacts =
+                    build_crate::build_crate_for_all_profile(krate, &config, &filter, clean)?;
                 rows.append(&mut report::report_artifacts(&artifacts));
+
+                if self_profile.is_some() {
+                    let mut profiles =
+                        self_profile::self_profile_for_all_profile(krate, &config, &filter)?;
+                    query_profiles.append(&mut profiles);
+                }
+            }
+            if rows.is_empty() {
+                log::warn!("nothing built, no cell matches the filters");
+            }
+            report::write_report(rows, Some(&config), &output, format)?;
+
+            if let Some(self_profile_output) = self_profile {
+                let baseline = config.baseline_toolchain()?.name.as_str();
+                let rows = report::report_self_profile(&query_profiles, baseline);
+                report::write_report(rows, Some(&config), &self_profile_output, format)?;
             }
-            write_json_to_output(rows, output)?;
+        }
+
+        Commands::Size {
+            config,
+            krate,
+            output,
+            format,
+            top,
+            diff,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            let krate = config.krate(&krate)?;
+            if dry_run {
+                // only reads built artifacts
+                return plan::print(&[]);
+            }
+
+            if diff.is_empty() {
+                let breakdowns = size::size_breakdown_for_all_profile(krate, &config)?;
+                let rows = report::report_size_breakdown(&breakdowns, top);
+                report::write_report(rows, Some(&config), &output, format)?;
+            } else {
+                let mut breakdowns = vec![];
+                for cell in diff.iter() {
+                    let (toolchain, profile) = cell.split_once(':').ok_or_else(|| {
+                        anyhow::anyhow!("{cell} is not in format toolchain:profile")
+                    })?;
+                    let toolchain = config.toolchain(toolchain)?;
+                    let profile = config.profile(profile)?;
+                    breakdowns.push(size::size_breakdown_step(
+                        krate, toolchain, profile, &config,
+                    )?);
+                }
+                let rows = report::report_size_diff(&breakdowns[0], &breakdowns[1], top);
+                report::write_report(rows, Some(&config), &output, format)?;
+            }
+        }
+
+        Commands::Asm {
+            config,
+            krate,
+            symbol,
+            output,
+            baseline,
+            side_by_side,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            let krate = config.krate(&krate)?;
+            if dry_run {
+                // only reads built artifacts
+                return plan::print(&[]);
+            }
+            let baseline = match baseline {
+                Some(baseline) => baseline,
+                None => config.baseline_toolchain()?.name.clone(),
+            };
+            let diff =
+                asm::asm_diff_for_all_profile(krate, &symbol, &baseline, side_by_side, &config)?;
+            if output.eq("-") {
+                print!("{}", diff);
+            } else {
+                std::fs::write(output, diff)?;
+            }
+        }
+
+        Commands::Repro {
+            config,
+            krate,
+            output,
+            format,
+            remap,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            let krate = config.krate(&krate)?;
+            if dry_run {
+                return plan::print(&repro::plan_repro_for_all_profile(krate, &config, remap)?);
+            }
+            let results = repro::repro_for_all_profile(krate, &config, remap)?;
+            let rows = report::report_repro_results(results);
+            report::write_report(rows, Some(&config), &output, format)?;
         }
 
         Commands::Run {
+            config,
+            filter,
+            output,
+            format,
+            percentiles,
+            rebuild,
+            samples,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            let filter = utils::CellFilter::from(filter);
+            if dry_run {
+                let mut steps = vec![];
+                for krate in config.crates.iter().filter(|k| filter.krate(k)) {
+                    steps.append(&mut run::plan_run_cmds(&filter, &config, krate, rebuild)?);
+                }
+                return plan::print(&steps);
+            }
+            let mut run_result = run::RunResult { results: vec![] };
+            for krate in config.crates.iter().filter(|k| filter.krate(k)) {
+                let mut krate_result = run::run_cmds(&filter, &config, krate, rebuild)?;
+                run_result.results.append(&mut krate_result.results);
+            }
+            if run_result.results.is_empty() {
+                log::warn!("nothing run, no cell matches the filters");
+            }
+            if let Some(samples) = samples {
+                let samples_format = if samples.ends_with(".csv") {
+                    report::OutputFormat::Csv
+                } else {
+                    report::OutputFormat::Jsonl
+                };
+                report::write_report(&run_result.results, Some(&config), &samples, samples_format)?;
+            }
+            let rows = report::report_run_results(run_result, &percentiles);
+            report::write_report(rows, Some(&config), &output, format)?;
+        }
+
+        Commands::Gate {
+            config,
+            results,
+            previous,
+            baseline,
+            output,
+            format,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            if dry_run {
+                // only reads result files
+                return plan::print(&[]);
+            }
+            let load = |files: &[String]| -> anyhow::Result<Vec<_>> {
+                let mut rows = vec![];
+                for file in files.iter() {
+                    rows.append(&mut report::load_report(std::path::Path::new(file))?);
+                }
+                Ok(rows)
+            };
+            let rows = load(&results)?;
+            let previous_rows = load(&previous)?;
+            let baseline = match baseline {
+                Some(baseline) => baseline,
+                None => config.baseline_toolchain()?.name.clone(),
+            };
+            let reference = if previous.is_empty() {
+                gate::Reference::Baseline(&baseline)
+            } else {
+                gate::Reference::Previous(&previous_rows)
+            };
+
+            let results = gate::check_gates(&rows, &reference, &config)?;
+            let total = results.len();
+            let failed = results.iter().filter(|r| !r.pass).count();
+            let rows = report::report_gate_results(results);
+            report::write_report(rows, Some(&config), &output, format)?;
+
+            if total == 0 {
+                log::warn!("no gate checked, are thresholds set in config?");
+            }
+            if failed > 0 {
+                anyhow::bail!("{failed} of {total} gates failed");
+            }
+        }
+
+        Commands::All {
+            config,
+            filter,
+            output,
+            format,
+            percentiles,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            let filter = utils::CellFilter::from(filter);
+            if dry_run {
+                return plan::print(&pipeline::plan_dry_run(&config, &filter)?);
+            }
+
+            let steps = pipeline::plan(&config, &filter)?;
+            pipeline::execute(&steps, &config)?;
+
+            let mut run_result = run::RunResult { results: vec![] };
+            for krate in config.crates.iter().filter(|k| filter.krate(k)) {
+                // pipeline has built everything needed
+                let mut krate_result = run::run_cmds(&filter, &config, krate, false)?;
+                run_result.results.append(&mut krate_result.results);
+            }
+            let rows = report::report_run_results(run_result, &percentiles);
+            report::write_report(rows, Some(&config), &output, format)?;
+        }
+
+        Commands::Exec { config, cell, cmd } => {
+            let config = config::load_from_file(config.as_str())?;
+            exec_in_cell(&config, &cell, &cmd, dry_run)?;
+        }
+
+        Commands::Shell { config, cell } => {
+            let config = config::load_from_file(config.as_str())?;
+            exec_in_cell(&config, &cell, &exec::shell_cmd(), dry_run)?;
+        }
+
+        Commands::Bisect {
             config,
             krate,
+            run,
+            good,
+            bad,
+            toolchain,
+            profile,
+            metric,
+            threshold,
+            state,
+            keep_toolchains,
             output,
+            format,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            let toolchain = match toolchain {
+                Some(toolchain) => config.toolchain(&toolchain)?,
+                None => config.baseline_toolchain()?,
+            };
+            let profile = match profile {
+                Some(profile) => profile,
+                None => toolchain.profiles.first().cloned().ok_or_else(|| {
+                    anyhow::anyhow!("toolchain {} has no profile", toolchain.name)
+                })?,
+            };
+            let opts = bisect::BisectOpts {
+                krate,
+                run,
+                toolchain: toolchain.name.clone(),
+                profile,
+                metric,
+                good,
+                bad,
+                threshold_pct: threshold,
+            };
+            let state_path = state
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(|| bisect::default_state_path(&opts, &config));
+
+            if dry_run {
+                return plan::print(&bisect::plan_bisect(
+                    opts,
+                    &state_path,
+                    keep_toolchains,
+                    &config,
+                )?);
+            }
+            let outcome = bisect::bisect(opts, &state_path, keep_toolchains, &config)?;
+            let rows = report::report_bisect(&outcome);
+            report::write_report(rows, Some(&config), &output, format)?;
+        }
+
+        Commands::BisectPatches {
+            config,
+            krate,
+            toolchain,
+            run,
             profile,
+            state,
+            keep_toolchains,
+            output,
+            format,
         } => {
             let config = config::load_from_file(config.as_str())?;
-            let krate = config
-                .crates
-                .iter()
-                .filter(|k| k.name.eq(&krate))
-                .nth(0)
-                .ok_or(anyhow::anyhow!("Not able to find crate"))?;
-            let run_result = run::run_cmds(profile.as_deref(), &config, &krate)?;
-            let rows = report::report_run_results(run_result);
-            write_json_to_output(rows, output)?;
+            let toolchain = config.toolchain(&toolchain)?;
+            let profile = match profile {
+                Some(profile) => profile,
+                None => toolchain.profiles.first().cloned().ok_or_else(|| {
+                    anyhow::anyhow!("toolchain {} has no profile", toolchain.name)
+                })?,
+            };
+            let opts = bisect::PatchSeriesOpts {
+                krate,
+                run,
+                toolchain: toolchain.name.clone(),
+                profile,
+            };
+            let state_path = state
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(|| bisect::default_patches_state_path(&opts, &config));
+
+            if dry_run {
+                return plan::print(&bisect::plan_bisect_patches(
+                    opts,
+                    &state_path,
+                    keep_toolchains,
+                    &config,
+                )?);
+            }
+            let state = bisect::bisect_patches(opts, &state_path, keep_toolchains, &config)?;
+            let rows = report::report_patches(&state);
+            report::write_report(rows, Some(&config), &output, format)?;
+        }
+
+        Commands::Compare {
+            config,
+            old,
+            new,
+            threshold,
+            output,
+            format,
+        } => {
+            let config = config::load_optional(config.as_deref())?;
+            if dry_run {
+                // only reads result files
+                return plan::print(&[]);
+            }
+            let old = report::load_report(std::path::Path::new(&old))?;
+            let new = report::load_report(std::path::Path::new(&new))?;
+            let deltas = compare::compare(&old, &new);
+            let rows = report::report_compare_results(deltas, threshold);
+
+            let is_terminal = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+            if matches!(format, report::OutputFormat::Table) && output.eq("-") && is_terminal {
+                println!("{}", report::render_compare_table(&rows)?);
+            } else {
+                report::write_report(rows, config.as_ref(), &output, format)?;
+            }
+        }
+
+        Commands::Aggregate {
+            config,
+            samples,
+            output,
+            format,
+            percentiles,
+        } => {
+            let config = config::load_optional(config.as_deref())?;
+            if dry_run {
+                // only reads samples file
+                return plan::print(&[]);
+            }
+            let run_result = run::load_samples(std::path::Path::new(&samples))?;
+            let rows = report::report_run_results(run_result, &percentiles);
+            report::write_report(rows, config.as_ref(), &output, format)?;
+        }
+
+        Commands::Test {
+            config,
+            krate,
+            output,
+            format,
+            baseline,
+            all,
+        } => {
+            let config = config::load_from_file(config.as_str())?;
+            let krate = config.krate(&krate)?;
+            if dry_run {
+                return plan::print(&test_crate::plan_test_crate_for_all_profile(
+                    krate, &config,
+                )?);
+            }
+            let baseline = match baseline {
+                Some(baseline) => config.toolchain(&baseline)?.name.clone(),
+                None => config.baseline_toolchain()?.name.clone(),
+            };
+            let results = test_crate::test_crate_for_all_profile(krate, &config)?;
+            let rows = report::report_test_results(&results, &baseline, all);
+            report::write_report(rows, Some(&config), &output, format)?;
         }
     }
 
     Ok(())
 }
 
+/// exec cmd in the cell, exit with its exit code if it fails
+fn exec_in_cell(
+    config: &config::Config,
+    cell: &CellArgs,
+    cmd: &[String],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let krate = config.krate(&cell.krate)?;
+    let toolchain = config.toolchain(&cell.toolchain)?;
+    let profile = config.profile(&cell.profile)?;
+    if dry_run {
+        return plan::print(&exec::plan_exec(krate, toolchain, profile, config, cmd)?);
+    }
+
+    let status = exec::exec(krate, toolchain, profile, config, cmd)?;
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+mod asm;
+mod bench;
+mod binary;
+mod bisect;
 mod bootstrap;
 mod build_crate;
 mod build_toolchain;
+mod compare;
 mod config;
+mod exec;
+mod gate;
 mod init;
+mod metadata;
+mod pipeline;
+mod plan;
 mod report;
+mod repro;
 mod run;
+mod self_profile;
+mod size;
+mod stats;
+mod test_crate;
 mod utils;
-
-/// write json to output file
-fn write_json_to_output(x: impl serde::Serialize, output: String) -> anyhow::Result<()> {
-    let content = serde_json::to_string_pretty(&x).unwrap();
-    if output.eq("-") {
-        println!("{}", content);
-    } else {
-        std::fs::write(output, content)?;
-    }
-    Ok(())
-}
@@ -0,0 +1,188 @@
+/// metadata attached to every report, so results can be traced back to the machine,
+/// compiler and sources which produced them
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    /// unix timestamp when report was created
+    pub created_at: u64,
+
+    pub hostname: String,
+    pub cpu_model: String,
+    pub cpu_cores: usize,
+    pub kernel: String,
+    pub memory_bytes: u64,
+
+    /// ctm's own version
+    pub ctm_version: String,
+
+    /// sha256 of config file, empty for reports made without config, e.g: compare
+    pub config_hash: String,
+
+    pub toolchains: Vec<ToolchainMetadata>,
+    pub crates: Vec<CrateMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolchainMetadata {
+    pub name: String,
+
+    /// rust rev resolved to commit hash, or as configured if rust repo is missing
+    pub rust_rev: String,
+
+    /// (patch file name, sha256) in apply order
+    pub patches: Vec<(String, String)>,
+
+    /// output of `rustc -vV`, `None` if toolchain is not installed
+    pub rustc_version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CrateMetadata {
+    pub name: String,
+
+    /// commit of crate checkout, `None` if not checked out or not a git repo
+    pub commit: Option<String>,
+}
+
+/// collect metadata of current machine and config, toolchains and crates are left empty
+/// without config
+pub fn collect(config: Option<&Config>) -> anyhow::Result<Metadata> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let cpu_model = cpuinfo
+        .lines()
+        .find(|l| l.starts_with("model name"))
+        .and_then(|l| l.split_once(':'))
+        .map(|(_, model)| model.trim().to_string())
+        .unwrap_or_default();
+
+    let meminfo = std::fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    let memory_bytes = meminfo
+        .lines()
+        .find(|l| l.starts_with("MemTotal:"))
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .unwrap_or_default()
+        * 1024;
+
+    let (config_hash, toolchains, crates) = match config {
+        Some(config) => (
+            sha256(&config.config_file)?,
+            toolchains(config),
+            crates(config),
+        ),
+        None => Default::default(),
+    };
+
+    Ok(Metadata {
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+        hostname: std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        cpu_model,
+        cpu_cores: std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+        kernel: std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        memory_bytes,
+        ctm_version: env!("CARGO_PKG_VERSION").to_string(),
+        config_hash,
+        toolchains,
+        crates,
+    })
+}
+
+fn toolchains(config: &Config) -> Vec<ToolchainMetadata> {
+    let rust_repo = config.global.rust_repo();
+    config
+        .toolchains
+        .iter()
+        .map(|t| {
+            let rust_rev = t.rust_rev.as_ref().unwrap_or(&config.global.rust_rev);
+            let resolved_rev = if rust_repo.exists() {
+                cmd_lib::run_fun!(git -C $rust_repo rev-parse $rust_rev)
+                    .unwrap_or_else(|_| rust_rev.clone())
+            } else {
+                rust_rev.clone()
+            };
+
+            let patches = t
+                .patches
+                .iter()
+                .map(|p| {
+                    let hash = sha256(&config.global.patches_root().join(p)).unwrap_or_default();
+                    (p.clone(), hash)
+                })
+                .collect();
+
+            let toolchain_name = &t.name;
+            let rustc_version = cmd_lib::run_fun!(
+                RUSTUP_TOOLCHAIN=$toolchain_name rustc -vV
+            )
+            .ok();
+
+            ToolchainMetadata {
+                name: t.name.clone(),
+                rust_rev: resolved_rev,
+                patches,
+                rustc_version,
+            }
+        })
+        .collect()
+}
+
+fn crates(config: &Config) -> Vec<CrateMetadata> {
+    config
+        .crates
+        .iter()
+        .map(|k| {
+            let folder = crate::utils::crate_folder(k, config);
+            let commit = if folder.join(".git").exists() {
+                cmd_lib::run_fun!(git -C $folder rev-parse HEAD).ok()
+            } else {
+                None
+            };
+            CrateMetadata {
+                name: k.name.clone(),
+                commit,
+            }
+        })
+        .collect()
+}
+
+/// sha256 of file content
+pub fn sha256(path: &std::path::Path) -> anyhow::Result<String> {
+    let output = cmd_lib::run_fun!(sha256sum $path)?;
+    Ok(output
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// sha256 of in memory content
+pub fn sha256_of(content: &str) -> anyhow::Result<String> {
+    use std::io::Write;
+
+    let mut child = std::process::Command::new("sha256sum")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(content.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!("sha256sum failed with {:?}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
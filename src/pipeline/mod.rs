@@ -0,0 +1,273 @@
+/// `ctm all`: bootstrap, build toolchains and build crates, skipping the steps which are
+/// up to date, so runs can be executed on fresh artifacts
+use crate::build_crate::is_up_to_date;
+use crate::build_toolchain::ToolChainOpts;
+use crate::config::{BuildMode, Config, CrateOpt};
+use crate::plan::PlanStep;
+use crate::utils::{self, CellFilter};
+use std::time::SystemTime;
+
+pub enum Step {
+    Bootstrap,
+    BuildToolchain {
+        toolchain: String,
+    },
+    /// release build of one cell
+    BuildCrate {
+        krate: String,
+        toolchain: String,
+        profile: String,
+    },
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Bootstrap => write!(f, "bootstrap"),
+            Step::BuildToolchain { toolchain } => write!(f, "build-toolchain {toolchain}"),
+            Step::BuildCrate {
+                krate,
+                toolchain,
+                profile,
+            } => write!(f, "build-crate {krate} {toolchain}_{profile}"),
+        }
+    }
+}
+
+/// a step which is out of date, and why
+pub struct PlannedStep {
+    pub step: Step,
+    pub reason: String,
+}
+
+/// find out the steps needed for the cells picked by filter, in execution order
+pub fn plan(config: &Config, filter: &CellFilter) -> anyhow::Result<Vec<PlannedStep>> {
+    let mut steps = vec![];
+
+    if !config.global.rust_repo().exists() {
+        steps.push(PlannedStep {
+            step: Step::Bootstrap,
+            reason: "rust repo missing".to_string(),
+        });
+    }
+
+    let mut stale_toolchains = vec![];
+    for toolchain in config.toolchains.iter() {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
+        let opts = ToolChainOpts::from_config(toolchain, &config.global, true);
+        if let Some(reason) = opts.staleness(&config.global.rust_repo())? {
+            stale_toolchains.push(toolchain.name.clone());
+            steps.push(PlannedStep {
+                step: Step::BuildToolchain {
+                    toolchain: toolchain.name.clone(),
+                },
+                reason,
+            });
+        }
+    }
+
+    for krate in config.crates.iter().filter(|k| filter.krate(k)) {
+        let source_mtime = source_mtime(krate, config)?;
+
+        for toolchain in config.toolchains.iter() {
+            if !filter.toolchain(&toolchain.name) {
+                continue;
+            }
+            let stamp_mtime = ToolChainOpts::from_config(toolchain, &config.global, true)
+                .stamp_path()
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok();
+
+            for profile in toolchain.profiles.iter() {
+                if !filter.profile(profile) {
+                    continue;
+                }
+                let profile = config.profile(profile)?;
+                let artifact = utils::artifact_path(krate, profile, toolchain, config);
+                let artifact_mtime = artifact.metadata().and_then(|m| m.modified()).ok();
+
+                let reason = match artifact_mtime {
+                    _ if stale_toolchains.contains(&toolchain.name) => "toolchain is rebuilt",
+                    None => "artifact missing",
+                    Some(t) if stamp_mtime > Some(t) => "artifact older than toolchain",
+                    Some(t) if source_mtime > Some(t) => "source changed",
+                    Some(_) if !is_up_to_date(krate, toolchain, profile, config)? => {
+                        "fingerprint changed"
+                    }
+                    Some(_) => continue,
+                };
+                steps.push(PlannedStep {
+                    step: Step::BuildCrate {
+                        krate: krate.name.clone(),
+                        toolchain: toolchain.name.clone(),
+                        profile: profile.name.clone(),
+                    },
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(steps)
+}
+
+/// execute planned steps in order
+pub fn execute(steps: &[PlannedStep], config: &Config) -> anyhow::Result<()> {
+    for planned in steps.iter() {
+        log::info!("{}: {}", planned.step, planned.reason);
+
+        match &planned.step {
+            Step::Bootstrap => crate::bootstrap::bootstrap(config)?,
+            Step::BuildToolchain { toolchain } => {
+                let toolchain = config.toolchain(toolchain)?;
+                let opts = ToolChainOpts::from_config(toolchain, &config.global, true);
+                crate::build_toolchain::build_toolchain(&config.global.rust_repo(), &opts)?;
+            }
+            Step::BuildCrate {
+                krate,
+                toolchain,
+                profile,
+            } => {
+                let krate = config.krate(krate)?;
+                crate::build_crate::build_crate_step(
+                    krate,
+                    config.toolchain(toolchain)?,
+                    config.profile(profile)?,
+                    BuildMode::Release,
+                    config,
+                    false,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// plan of `ctm all`: every step with the ones up to date marked cached, then the runs
+pub fn plan_dry_run(config: &Config, filter: &CellFilter) -> anyhow::Result<Vec<PlanStep>> {
+    let planned = plan(config, filter)?;
+    let reason_of = |step: &Step| {
+        let name = step.to_string();
+        planned
+            .iter()
+            .find(|p| p.step.to_string() == name)
+            .map(|p| p.reason.clone())
+    };
+    let with_reason = |mut steps: Vec<PlanStep>, reason: String| {
+        if let Some(first) = steps.first_mut() {
+            first.notes.insert(0, format!("out of date: {reason}"));
+        }
+        steps
+    };
+
+    let mut steps = vec![];
+    match reason_of(&Step::Bootstrap) {
+        Some(reason) => steps.append(&mut with_reason(
+            crate::bootstrap::plan_bootstrap(config),
+            reason,
+        )),
+        None => {
+            let mut step = PlanStep::new(Step::Bootstrap.to_string());
+            step.cached = Some("rust repo exists".to_string());
+            steps.push(step);
+        }
+    }
+
+    let mut stale_toolchains = vec![];
+    for toolchain in config.toolchains.iter() {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
+        let step = Step::BuildToolchain {
+            toolchain: toolchain.name.clone(),
+        };
+        match reason_of(&step) {
+            Some(reason) => {
+                stale_toolchains.push(toolchain.name.clone());
+                let opts = ToolChainOpts::from_config(toolchain, &config.global, true);
+                steps.append(&mut with_reason(
+                    crate::build_toolchain::plan_build_toolchain(
+                        &config.global.rust_repo(),
+                        &[opts],
+                    )?,
+                    reason,
+                ));
+            }
+            None => {
+                let mut step = PlanStep::new(step.to_string());
+                step.cached = Some("up to date".to_string());
+                steps.push(step);
+            }
+        }
+    }
+
+    for krate in config.crates.iter().filter(|k| filter.krate(k)) {
+        let mut checkout = Some(utils::plan_checkout(krate, config)?);
+        let mut runs = vec![];
+
+        for toolchain in config.toolchains.iter() {
+            if !filter.toolchain(&toolchain.name) {
+                continue;
+            }
+            for profile in toolchain.profiles.iter() {
+                if !filter.profile(profile) {
+                    continue;
+                }
+                let profile = config.profile(profile)?;
+                let step = Step::BuildCrate {
+                    krate: krate.name.clone(),
+                    toolchain: toolchain.name.clone(),
+                    profile: profile.name.clone(),
+                };
+                let reason = reason_of(&step);
+                let mut build = crate::build_crate::plan_build_crate_step(
+                    krate,
+                    toolchain,
+                    profile,
+                    BuildMode::Release,
+                    config,
+                    false,
+                )?;
+                match reason {
+                    Some(reason) => {
+                        steps.extend(checkout.take());
+                        build.notes.insert(0, format!("out of date: {reason}"));
+                        // fingerprint is unknown until the toolchain is built
+                        if stale_toolchains.contains(&toolchain.name) {
+                            build.cached = None;
+                        }
+                    }
+                    None => build.cached = Some("up to date".to_string()),
+                }
+                steps.push(build);
+
+                runs.append(&mut crate::run::plan_run_samples(
+                    krate, toolchain, profile, config, filter,
+                )?);
+            }
+        }
+        steps.append(&mut runs);
+    }
+
+    Ok(steps)
+}
+
+/// when crate source last changed: newest file of a local crate, or HEAD commit time of
+/// a git crate. `None` if not checked out yet
+fn source_mtime(krate: &CrateOpt, config: &Config) -> anyhow::Result<Option<SystemTime>> {
+    if let Some(path) = utils::crate_source_path(krate, config) {
+        return utils::newest_mtime(&path, &["target", ".git"]);
+    }
+
+    let folder = utils::crate_folder(krate, config);
+    if !folder.exists() {
+        return Ok(None);
+    }
+    let commit_time = cmd_lib::run_fun!(git -C $folder log -1 --format=%ct)?;
+    Ok(Some(
+        SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(commit_time.trim().parse()?),
+    ))
+}
@@ -0,0 +1,226 @@
+/// `--dry-run`: each command describes what it executes as plan steps, which are printed
+/// as an annotated shell script instead of being executed. Commands execute the same steps
+/// they plan, so a plan can't disagree with what runs
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// rough size of a rust checkout with submodules
+pub const RUST_REPO_ESTIMATE: u64 = 6 << 30;
+
+/// rough size of rust repo's build folder, shared by all toolchains built from it
+pub const RUST_BUILD_ESTIMATE: u64 = 15 << 30;
+
+/// rough size of an installed toolchain, if no other toolchain is installed to compare
+pub const TOOLCHAIN_ESTIMATE: u64 = 2 << 30;
+
+#[derive(Debug, Default)]
+pub struct PlanStep {
+    /// what the step is for, e.g: `build-crate dust base_minsize release`
+    pub title: String,
+
+    /// working directory of cmds
+    pub cwd: Option<PathBuf>,
+
+    /// environment variables set for cmds, besides the inherited ones
+    pub env: BTreeMap<String, String>,
+
+    /// shell cmds in execution order
+    pub cmds: Vec<String>,
+
+    /// shell cmds executed after `cmds` even if one of them fails, e.g: reverting a patch
+    pub cleanup: Vec<String>,
+
+    /// index of the cmd whose duration is measured, e.g: the build cmd of build-crate
+    pub timed: Option<usize>,
+
+    /// things cmds don't tell, e.g: sample count, timeout
+    pub notes: Vec<String>,
+
+    /// why the step is skipped, `None` if it would be executed
+    pub cached: Option<String>,
+
+    /// estimated disk space the step takes in addition, `None` if unknown
+    pub disk_bytes: Option<u64>,
+}
+
+impl PlanStep {
+    pub fn new(title: impl Into<String>) -> Self {
+        PlanStep {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    /// bash running `cmd` in cwd with env, stdout and stderr are inherited
+    pub fn command(&self, cmd: &str) -> std::process::Command {
+        let mut command = std::process::Command::new("bash");
+        command.args(["-c", cmd]).envs(&self.env);
+        if let Some(cwd) = self.cwd.as_ref() {
+            command.current_dir(cwd);
+        }
+        command
+    }
+
+    /// execute cmds one by one and stop at the first failing one, then the cleanup cmds.
+    /// Returns how long each executed cmd took
+    pub fn execute(&self) -> anyhow::Result<Vec<std::time::Duration>> {
+        let run = |cmd: &String| -> anyhow::Result<std::time::Duration> {
+            log::info!("{}: {}", self.title, cmd.lines().next().unwrap_or_default());
+            let start = std::time::Instant::now();
+            let status = self.command(cmd).status()?;
+            let duration = start.elapsed();
+            if !status.success() {
+                anyhow::bail!("`{cmd}` of {} failed with {status}", self.title);
+            }
+            Ok(duration)
+        };
+
+        let durations = self
+            .cmds
+            .iter()
+            .map(run)
+            .collect::<anyhow::Result<Vec<_>>>();
+        for cmd in self.cleanup.iter() {
+            match (run(cmd), durations.is_ok()) {
+                (Err(e), true) => return Err(e),
+                // the failure of cmds is the one to report
+                (Err(e), false) => log::warn!("{e}"),
+                (Ok(_), _) => {}
+            }
+        }
+        durations
+    }
+
+    /// execute the step and return the duration of the timed cmd
+    pub fn execute_timed(&self) -> anyhow::Result<std::time::Duration> {
+        let timed = self
+            .timed
+            .ok_or_else(|| anyhow::anyhow!("{} has no timed cmd", self.title))?;
+        Ok(self.execute()?[timed])
+    }
+}
+
+/// estimate how much disk a build into `folder` takes: nothing if it exists, otherwise as
+/// much as the largest existing sibling folder, `None` if there is no sibling
+pub fn estimate_new_folder(folder: &std::path::Path) -> anyhow::Result<Option<u64>> {
+    if folder.exists() {
+        return Ok(Some(0));
+    }
+    let parent = match folder.parent() {
+        Some(parent) if parent.exists() => parent,
+        _ => return Ok(None),
+    };
+    let mut largest = None;
+    for entry in std::fs::read_dir(parent)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            largest = largest.max(Some(crate::utils::dir_size(&entry.path())?));
+        }
+    }
+    Ok(largest)
+}
+
+/// print the plan to stdout, for `--dry-run`
+pub fn print(steps: &[PlanStep]) -> anyhow::Result<()> {
+    println!("{}", render(steps));
+    Ok(())
+}
+
+/// render steps as an annotated shell script, with total estimated disk usage at the end
+pub fn render(steps: &[PlanStep]) -> String {
+    if steps.is_empty() {
+        return "# nothing to execute".to_string();
+    }
+
+    let mut lines = vec![];
+    let mut total_disk_bytes = 0;
+    let mut unknown_disk = 0;
+
+    for step in steps.iter() {
+        match step.cached.as_ref() {
+            Some(reason) => lines.push(format!("# {} (cached: {})", step.title, reason)),
+            None => lines.push(format!("# {}", step.title)),
+        }
+        for note in step.notes.iter() {
+            lines.push(format!("# {note}"));
+        }
+        if let Some(cmd) = step.timed.and_then(|timed| step.cmds.get(timed)) {
+            lines.push(format!(
+                "# timed: {}",
+                cmd.lines().next().unwrap_or_default()
+            ));
+        }
+        if step.cached.is_none() {
+            match step.disk_bytes {
+                Some(0) => {}
+                Some(bytes) => {
+                    total_disk_bytes += bytes;
+                    lines.push(format!("# estimated disk usage: {}", human_size(bytes)));
+                }
+                None => unknown_disk += 1,
+            }
+        }
+
+        // each step in a subshell, so cwd and env don't leak into the next one
+        let mut body = vec![];
+        if let Some(cwd) = step.cwd.as_ref() {
+            body.push(format!("cd {}", quote(cwd.to_str().unwrap())));
+        }
+        for (k, v) in step.env.iter() {
+            body.push(format!("export {k}={}", quote(v)));
+        }
+        body.extend(step.cmds.iter().cloned());
+        body.extend(step.cleanup.iter().cloned());
+
+        if !body.is_empty() {
+            let prefix = if step.cached.is_some() { "# " } else { "" };
+            lines.push(format!("{prefix}("));
+            for cmd in body.iter() {
+                // continuation lines are kept as is, e.g: heredoc content
+                for (i, line) in cmd.lines().enumerate() {
+                    let indent = if i == 0 { "  " } else { "" };
+                    lines.push(format!("{prefix}{indent}{line}"));
+                }
+            }
+            lines.push(format!("{prefix})"));
+        }
+        lines.push(String::new());
+    }
+
+    let executed = steps.iter().filter(|s| s.cached.is_none()).count();
+    lines.push(format!(
+        "# steps: {}, cached: {}, estimated disk usage: {}{}",
+        steps.len(),
+        steps.len() - executed,
+        human_size(total_disk_bytes),
+        if unknown_disk > 0 {
+            format!(" plus unknown for {unknown_disk} steps")
+        } else {
+            String::new()
+        }
+    ));
+    lines.join("\n")
+}
+
+/// quote for shell if needed
+pub fn quote(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c));
+    if plain {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
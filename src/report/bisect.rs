@@ -0,0 +1,64 @@
+use crate::bisect::{BisectOutcome, Verdict};
+use serde::Serialize;
+
+/// verdict of a commit in report, untestable ones failed to build or run
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CommitVerdict {
+    Good,
+    Bad,
+    Untestable,
+}
+
+impl From<Verdict> for CommitVerdict {
+    fn from(verdict: Verdict) -> Self {
+        match verdict {
+            Verdict::Good => CommitVerdict::Good,
+            Verdict::Bad => CommitVerdict::Bad,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BisectReportRow {
+    index: usize,
+    commit: String,
+    subject: String,
+    verdict: CommitVerdict,
+    first_bad: bool,
+    samples: usize,
+    mean: f64,
+    change_pct: f64,
+}
+
+/// one row per tested commit, untestable ones included, in commit order
+pub fn report_bisect(outcome: &BisectOutcome) -> Vec<BisectReportRow> {
+    let state = &outcome.state;
+    let mut rows = state
+        .tested
+        .clone()
+        .into_iter()
+        .map(|t| BisectReportRow {
+            index: t.index,
+            verdict: state.verdict(&t.values).into(),
+            first_bad: t.index == outcome.first_bad,
+            samples: t.values.len(),
+            mean: crate::stats::mean(&t.values),
+            change_pct: (state.change_pct(&t.values) * 100.0).round() / 100.0,
+            commit: t.commit,
+            subject: t.subject,
+        })
+        .collect::<Vec<_>>();
+    rows.extend(state.untestable.iter().map(|u| BisectReportRow {
+        index: u.index,
+        commit: u.commit.clone(),
+        subject: u.subject.clone(),
+        verdict: CommitVerdict::Untestable,
+        first_bad: false,
+        samples: 0,
+        mean: 0.0,
+        change_pct: 0.0,
+    }));
+    rows.sort_by_key(|r| r.index);
+    rows
+}
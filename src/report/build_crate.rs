@@ -1,4 +1,5 @@
 use crate::build_crate::Artifact;
+use crate::config::BuildMode;
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -6,23 +7,40 @@ pub struct BuildCrateReportRow {
     toolchain: String,
     profile: String,
     krate: String,
-    binary_size: u64,
-    path: String,
+    mode: BuildMode,
+    build_duration_ms: u64,
+    binary_size: Option<u64>,
+    unstripped_size: Option<u64>,
+    path: Option<String>,
+    cached: bool,
 }
 
 pub fn report_artifacts(artifacts: &[Artifact]) -> Vec<BuildCrateReportRow> {
     artifacts
         .iter()
         .map(|a| {
-            let meta = std::fs::metadata(&a.output_path).unwrap();
-            let binary_size = meta.len();
+            let binary_size = a
+                .output_path
+                .as_ref()
+                .map(|p| std::fs::metadata(p).unwrap().len());
+            let unstripped_size = a
+                .unstripped_path
+                .as_ref()
+                .map(|p| std::fs::metadata(p).unwrap().len());
 
             BuildCrateReportRow {
                 toolchain: a.toolchain.clone(),
                 profile: a.profile.clone(),
                 krate: a.crate_name.clone(),
+                mode: a.mode,
+                build_duration_ms: a.build_duration_ms,
                 binary_size,
-                path: a.output_path.to_str().unwrap().to_string(),
+                unstripped_size,
+                path: a
+                    .output_path
+                    .as_ref()
+                    .map(|p| p.to_str().unwrap().to_string()),
+                cached: a.cached,
             }
         })
         .collect::<Vec<_>>()
@@ -0,0 +1,97 @@
+use crate::compare::MetricDelta;
+use crate::stats;
+use itertools::Itertools;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct CompareReportRow {
+    toolchain: String,
+    profile: String,
+    krate: String,
+    name: String,
+    metric: String,
+    old: Option<f64>,
+    new: Option<f64>,
+    delta_pct: f64,
+    change: Change,
+}
+
+/// direction of change, every compared metric is lower is better
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Improved,
+    Regressed,
+    Unchanged,
+}
+
+/// one row per compared metric, followed by geometric mean of new / old ratios for each
+/// (toolchain, profile, metric). Changes within `threshold_pct` are unchanged
+pub fn report_compare_results(
+    deltas: Vec<MetricDelta>,
+    threshold_pct: f64,
+) -> Vec<CompareReportRow> {
+    let change = |delta_pct: f64| {
+        if delta_pct > threshold_pct {
+            Change::Regressed
+        } else if delta_pct < -threshold_pct {
+            Change::Improved
+        } else {
+            Change::Unchanged
+        }
+    };
+    let round = |pct: f64| (pct * 100.0).round() / 100.0;
+
+    let mut rows = vec![];
+    for d in deltas.iter() {
+        let delta_pct = (d.ratio() - 1.0) * 100.0;
+        rows.push(CompareReportRow {
+            toolchain: d.toolchain.clone(),
+            profile: d.profile.clone(),
+            krate: d.krate.clone(),
+            name: d.name.clone(),
+            metric: d.metric.clone(),
+            old: Some(d.old),
+            new: Some(d.new),
+            delta_pct: round(delta_pct),
+            change: change(delta_pct),
+        });
+    }
+
+    for ((toolchain, profile, metric), group) in &deltas
+        .iter()
+        .sorted_by_key(|d| (d.toolchain.clone(), d.profile.clone(), d.metric.clone()))
+        .group_by(|d| (d.toolchain.clone(), d.profile.clone(), d.metric.clone()))
+    {
+        let ratios = group.map(|d| d.ratio()).collect::<Vec<_>>();
+        let delta_pct = (stats::geomean(&ratios) - 1.0) * 100.0;
+        rows.push(CompareReportRow {
+            toolchain,
+            profile,
+            krate: String::new(),
+            name: "geomean".to_string(),
+            metric,
+            old: None,
+            new: None,
+            delta_pct: round(delta_pct),
+            change: change(delta_pct),
+        });
+    }
+
+    rows
+}
+
+/// table format with regressed rows in red and improved rows in green
+pub fn render_compare_table(rows: &[CompareReportRow]) -> anyhow::Result<String> {
+    let table = super::format::to_table(&super::format::to_rows(rows)?);
+    let lines = table.lines().enumerate().map(|(i, line)| {
+        // first 2 lines are header and separator
+        let color = match rows.get(i.wrapping_sub(2)).map(|r| r.change) {
+            Some(Change::Regressed) => "\x1b[31m",
+            Some(Change::Improved) => "\x1b[32m",
+            _ => return line.to_string(),
+        };
+        format!("{color}{line}\x1b[0m")
+    });
+    Ok(lines.collect::<Vec<_>>().join("\n"))
+}
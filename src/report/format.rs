@@ -0,0 +1,421 @@
+/// render report rows in the format chosen by `--format`. Rows can be any list of
+/// serializable flat structs, columns follow the order of struct fields
+use crate::config::Config;
+use crate::metadata::Metadata;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+pub enum OutputFormat {
+    /// pretty json with metadata
+    Json,
+    /// one json object per row, after a `{"metadata": ..}` line
+    Jsonl,
+    /// rows after a `# metadata: ..` comment line
+    Csv,
+    /// markdown table, metadata in a collapsed block
+    Markdown,
+    /// aligned plain text table, after a `# metadata: ..` comment line
+    Table,
+    /// standalone page with sortable tables and charts
+    Html,
+}
+
+/// report written by json format, rows with metadata about how they are produced
+#[derive(Serialize)]
+struct Report<T> {
+    metadata: Metadata,
+    rows: T,
+}
+
+/// first line of jsonl
+#[derive(Serialize)]
+struct MetadataLine<'a> {
+    metadata: &'a Metadata,
+}
+
+/// one report row, keeps fields in serialized order
+pub(super) struct Row(pub Vec<(String, Value)>);
+
+impl Row {
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.0.iter().find(|(k, _)| k == column).map(|(_, v)| v)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Row {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RowVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for RowVisitor {
+            type Value = Row;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a report row object")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Row, A::Error> {
+                let mut fields = vec![];
+                while let Some(field) = map.next_entry::<String, Value>()? {
+                    fields.push(field);
+                }
+                Ok(Row(fields))
+            }
+        }
+
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+impl Serialize for Row {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in self.0.iter() {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+/// write rows to output path, `-` means stdout. Metadata is about config's toolchains and
+/// crates if there is a config
+pub fn write_report(
+    rows: impl Serialize,
+    config: Option<&Config>,
+    output: &str,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let metadata = crate::metadata::collect(config)?;
+
+    let content = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&Report { metadata, rows })?,
+        format => {
+            let rows = to_rows(rows)?;
+            match format {
+                OutputFormat::Jsonl => format!(
+                    "{}\n{}",
+                    serde_json::to_string(&MetadataLine {
+                        metadata: &metadata
+                    })?,
+                    to_jsonl(&rows)?
+                ),
+                OutputFormat::Csv => format!("{}\n{}", metadata_comment(&metadata)?, to_csv(&rows)),
+                OutputFormat::Markdown => to_markdown(&rows, &metadata)?,
+                OutputFormat::Table => {
+                    format!("{}\n{}", metadata_comment(&metadata)?, to_table(&rows))
+                }
+                _ => super::html::to_html(&rows, &metadata)?,
+            }
+        }
+    };
+
+    if output.eq("-") {
+        println!("{}", content);
+    } else {
+        std::fs::write(output, content)?;
+    }
+    Ok(())
+}
+
+/// comment line carrying metadata as compact json, for csv and table
+fn metadata_comment(metadata: &Metadata) -> anyhow::Result<String> {
+    Ok(format!("# metadata: {}", serde_json::to_string(metadata)?))
+}
+
+/// whether a line of jsonl, csv or table report is the metadata line written before rows
+pub fn is_metadata_line(line: &str) -> bool {
+    line.starts_with("{\"metadata\":") || line.starts_with("# metadata: ")
+}
+
+pub(super) fn to_rows(rows: impl Serialize) -> anyhow::Result<Vec<Row>> {
+    // round trip through string, `Value` objects would lose field order
+    serde_json::from_str(&serde_json::to_string(&rows)?)
+        .map_err(|e| anyhow::anyhow!("report is not a list of rows: {e}"))
+}
+
+/// union of row fields, in the order they first appear
+pub(super) fn columns(rows: &[Row]) -> Vec<String> {
+    let mut columns: Vec<String> = vec![];
+    for row in rows.iter() {
+        for (k, _) in row.0.iter() {
+            if !columns.contains(k) {
+                columns.push(k.clone());
+            }
+        }
+    }
+    columns
+}
+
+/// cell text of a value, empty for missing values, nested values as compact json
+pub(super) fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    }
+}
+
+fn to_jsonl(rows: &[Row]) -> anyhow::Result<String> {
+    let lines = rows
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines.join("\n"))
+}
+
+fn to_csv(rows: &[Row]) -> String {
+    let columns = columns(rows);
+    let escape = |s: String| {
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s
+        }
+    };
+
+    let mut lines = vec![columns
+        .iter()
+        .cloned()
+        .map(escape)
+        .collect::<Vec<_>>()
+        .join(",")];
+    for row in rows.iter() {
+        let cells = columns.iter().map(|c| escape(cell(row.get(c))));
+        lines.push(cells.collect::<Vec<_>>().join(","));
+    }
+    lines.join("\n")
+}
+
+fn to_markdown(rows: &[Row], metadata: &Metadata) -> anyhow::Result<String> {
+    let columns = columns(rows);
+    let escape = |s: String| s.replace('|', "\\|").replace('\n', "<br>");
+
+    let mut lines = vec![
+        format!("| {} |", columns.join(" | ")),
+        format!("|{}", "---|".repeat(columns.len())),
+    ];
+    for row in rows.iter() {
+        let cells = columns.iter().map(|c| escape(cell(row.get(c))));
+        lines.push(format!("| {} |", cells.collect::<Vec<_>>().join(" | ")));
+    }
+
+    lines.push(String::new());
+    lines.push("<details><summary>metadata</summary>".to_string());
+    lines.push(String::new());
+    lines.push("```json".to_string());
+    lines.push(serde_json::to_string_pretty(metadata)?);
+    lines.push("```".to_string());
+    lines.push("</details>".to_string());
+    Ok(lines.join("\n"))
+}
+
+pub(super) fn to_table(rows: &[Row]) -> String {
+    let columns = columns(rows);
+    let cells = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|c| cell(row.get(c)).replace('\n', " "))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(c.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    // numbers are right aligned
+    let numeric = columns
+        .iter()
+        .map(|c| {
+            rows.iter()
+                .all(|row| matches!(row.get(c), None | Some(Value::Null | Value::Number(_))))
+        })
+        .collect::<Vec<_>>();
+
+    let format_line = |line: &[String]| {
+        line.iter()
+            .enumerate()
+            .map(|(i, s)| {
+                if numeric[i] {
+                    format!("{:>width$}", s, width = widths[i])
+                } else {
+                    format!("{:<width$}", s, width = widths[i])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![
+        format_line(&columns),
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("  "),
+    ];
+    lines.extend(cells.iter().map(|row| format_line(row)));
+    lines.join("\n")
+}
+
+/// parse csv written by `--format csv` into records of (column, cell), the metadata line
+/// is skipped
+pub fn parse_csv(content: &str) -> anyhow::Result<Vec<Vec<(String, String)>>> {
+    let content = match content.split_once('\n') {
+        Some((first, rest)) if is_metadata_line(first) => rest,
+        _ => content,
+    };
+    let mut lines = vec![];
+    let mut line = vec![];
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => line.push(std::mem::take(&mut cell)),
+            ('\n', false) => {
+                line.push(std::mem::take(&mut cell));
+                lines.push(std::mem::take(&mut line));
+            }
+            (c, _) => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !line.is_empty() {
+        line.push(cell);
+        lines.push(line);
+    }
+
+    let mut lines = lines.into_iter();
+    let header = match lines.next() {
+        Some(header) => header,
+        None => return Ok(vec![]),
+    };
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            if line.len() != header.len() {
+                anyhow::bail!(
+                    "csv line {} has {} cells, expect {}",
+                    i + 2,
+                    line.len(),
+                    header.len()
+                );
+            }
+            Ok(header.iter().cloned().zip(line).collect())
+        })
+        .collect()
+}
+
+/// load rows of a saved report, written with `--format json` (with or without metadata)
+/// or `--format jsonl`
+pub fn load_report(path: &std::path::Path) -> anyhow::Result<Vec<serde_json::Map<String, Value>>> {
+    let content = std::fs::read_to_string(path)?;
+    let rows = match serde_json::from_str::<Value>(&content) {
+        Ok(Value::Object(mut report)) => match report.remove("rows") {
+            Some(rows) => serde_json::from_value(rows)?,
+            // jsonl of metadata only
+            None if report.contains_key("metadata") => vec![],
+            // a single jsonl row
+            None => vec![report],
+        },
+        Ok(rows) => serde_json::from_value(rows)?,
+        Err(_) => content
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !is_metadata_line(l))
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    Ok(rows)
+}
+
+/// (krate, cmd or mode, toolchain, profile) identifying a cell of saved `run` or
+/// `build-crate` rows, `None` for other rows
+pub fn row_key(row: &serde_json::Map<String, Value>) -> Option<(&str, &str, &str, &str)> {
+    let field = |name: &str| row.get(name).and_then(|v| v.as_str());
+    Some((
+        field("krate")?,
+        field("cmd").or_else(|| field("mode"))?,
+        field("toolchain")?,
+        field("profile")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_round_trip() {
+        #[derive(Serialize)]
+        struct TestRow {
+            krate: &'static str,
+            cmd: &'static str,
+            binary_size: u64,
+            stop_reason: Option<&'static str>,
+        }
+        let rows = to_rows(vec![
+            TestRow {
+                krate: "dust",
+                cmd: "walk, home",
+                binary_size: 1024,
+                stop_reason: None,
+            },
+            TestRow {
+                krate: "say \"hi\"",
+                cmd: "two\nlines",
+                binary_size: 2048,
+                stop_reason: Some("count"),
+            },
+        ])
+        .unwrap();
+        let csv = format!("# metadata: {{}}\n{}", to_csv(&rows));
+
+        let cells =
+            |record: &[(String, String)]| record.iter().map(|(_, c)| c.clone()).collect::<Vec<_>>();
+        let records = parse_csv(&csv).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0]
+                .iter()
+                .map(|(c, _)| c.as_str())
+                .collect::<Vec<_>>(),
+            ["krate", "cmd", "binary_size", "stop_reason"]
+        );
+        assert_eq!(cells(&records[0]), ["dust", "walk, home", "1024", ""]);
+        assert_eq!(
+            cells(&records[1]),
+            ["say \"hi\"", "two\nlines", "2048", "count"]
+        );
+    }
+
+    #[test]
+    fn csv_with_missing_cell() {
+        let err = parse_csv("a,b\n1,2\n3\n").unwrap_err();
+        assert_eq!(err.to_string(), "csv line 3 has 1 cells, expect 2");
+        assert!(parse_csv("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn metadata_lines() {
+        assert!(is_metadata_line(r#"{"metadata":{"hostname":"vm"}}"#));
+        assert!(is_metadata_line("# metadata: {}"));
+        assert!(!is_metadata_line(r#"{"krate":"dust"}"#));
+    }
+}
@@ -0,0 +1,37 @@
+use crate::gate::GateResult;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct GateReportRow {
+    toolchain: String,
+    profile: String,
+    krate: String,
+    name: String,
+    metric: String,
+    value: f64,
+    reference: f64,
+    change_pct: f64,
+    max_pct: f64,
+    status: &'static str,
+}
+
+/// one row per checked gate, failed ones first
+pub fn report_gate_results(results: Vec<GateResult>) -> Vec<GateReportRow> {
+    let mut results = results;
+    results.sort_by_key(|r| r.pass);
+    results
+        .into_iter()
+        .map(|r| GateReportRow {
+            toolchain: r.toolchain,
+            profile: r.profile,
+            krate: r.krate,
+            name: r.name,
+            metric: r.metric,
+            value: r.value,
+            reference: r.reference,
+            change_pct: (r.change_pct * 100.0).round() / 100.0,
+            max_pct: r.max_pct,
+            status: if r.pass { "pass" } else { "FAIL" },
+        })
+        .collect()
+}
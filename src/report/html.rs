@@ -0,0 +1,226 @@
+/// standalone html report: sortable table of rows, inline svg charts of binary sizes,
+/// build durations and run duration distributions per toolchain/profile
+use super::format::{cell, columns, Row};
+use crate::metadata::Metadata;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; font-size: 13px; }
+th, td { border: 1px solid #ccc; padding: 3px 8px; }
+th { background: #eee; cursor: pointer; user-select: none; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+svg text { font-size: 12px; font-family: sans-serif; }
+"#;
+
+/// click a header to sort by it, click again to reverse
+const SORT_SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach((th, i) => {
+  th.addEventListener("click", () => {
+    const tbody = th.closest("table").tBodies[0];
+    const asc = th.dataset.order !== "asc";
+    th.closest("tr").querySelectorAll("th").forEach(h => delete h.dataset.order);
+    th.dataset.order = asc ? "asc" : "desc";
+    const key = tr => tr.cells[i].textContent;
+    const rows = Array.from(tbody.rows).sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      const [nx, ny] = [parseFloat(x), parseFloat(y)];
+      const c = !isNaN(nx) && !isNaN(ny) ? nx - ny : x.localeCompare(y);
+      return asc ? c : -c;
+    });
+    rows.forEach(tr => tbody.appendChild(tr));
+  });
+});
+"#;
+
+/// columns identifying a row in charts, in label order
+const LABEL_COLUMNS: [&str; 5] = ["krate", "cmd", "mode", "toolchain", "profile"];
+
+/// columns drawn as bar charts
+const BAR_COLUMNS: [&str; 4] = [
+    "binary_size",
+    "unstripped_size",
+    "build_duration_ms",
+    "duration_ns_mean",
+];
+
+const CHART_WIDTH: f64 = 900.0;
+const LABEL_WIDTH: f64 = 320.0;
+const BAR_HEIGHT: f64 = 18.0;
+
+pub fn to_html(rows: &[Row], metadata: &Metadata) -> anyhow::Result<String> {
+    let mut body = vec!["<h1>ctm report</h1>".to_string()];
+
+    for column in BAR_COLUMNS.iter() {
+        if let Some(chart) = bar_chart(rows, column) {
+            body.push(format!("<h2>{}</h2>", column));
+            body.push(chart);
+        }
+    }
+    if let Some(chart) = duration_chart(rows) {
+        body.push("<h2>duration distribution (ns)</h2>".to_string());
+        body.push(chart);
+    }
+
+    body.push("<h2>rows</h2>".to_string());
+    body.push(table(rows));
+
+    body.push(format!(
+        "<details><summary>metadata</summary><pre>{}</pre></details>",
+        escape(&serde_json::to_string_pretty(metadata)?)
+    ));
+
+    Ok(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>ctm report</title>\n<style>{}</style>\n</head>\n<body>\n{}\n<script>{}</script>\n</body>\n</html>",
+        STYLE,
+        body.join("\n"),
+        SORT_SCRIPT
+    ))
+}
+
+fn table(rows: &[Row]) -> String {
+    let columns = columns(rows);
+    let mut lines = vec![
+        "<table class=\"sortable\">".to_string(),
+        "<thead><tr>".to_string(),
+    ];
+    for c in columns.iter() {
+        lines.push(format!("<th>{}</th>", escape(c)));
+    }
+    lines.push("</tr></thead>".to_string());
+    lines.push("<tbody>".to_string());
+    for row in rows.iter() {
+        lines.push("<tr>".to_string());
+        for c in columns.iter() {
+            let value = row.get(c);
+            let class = if matches!(value, Some(serde_json::Value::Number(_))) {
+                " class=\"num\""
+            } else {
+                ""
+            };
+            lines.push(format!("<td{}>{}</td>", class, escape(&cell(value))));
+        }
+        lines.push("</tr>".to_string());
+    }
+    lines.push("</tbody>".to_string());
+    lines.push("</table>".to_string());
+    lines.join("\n")
+}
+
+/// label of row in charts, e.g: `dust fib30 base/minsize`
+fn label(row: &Row) -> String {
+    LABEL_COLUMNS
+        .iter()
+        .filter_map(|c| row.get(c).and_then(|v| v.as_str()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn number(row: &Row, column: &str) -> Option<f64> {
+    row.get(column).and_then(|v| v.as_f64())
+}
+
+/// horizontal bar per row, `None` if no row has a number in column
+fn bar_chart(rows: &[Row], column: &str) -> Option<String> {
+    let bars = rows
+        .iter()
+        .filter_map(|row| Some((label(row), number(row, column)?)))
+        .collect::<Vec<_>>();
+    let max = bars.iter().map(|(_, v)| *v).fold(0.0, f64::max);
+    if bars.is_empty() || max <= 0.0 {
+        return None;
+    }
+
+    let scale = (CHART_WIDTH - LABEL_WIDTH - 100.0) / max;
+    let mut svg = svg_header(bars.len());
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let y = i as f64 * (BAR_HEIGHT + 4.0);
+        svg.push(format!(
+            "<text x=\"0\" y=\"{:.1}\">{}</text>",
+            y + BAR_HEIGHT - 5.0,
+            escape(label)
+        ));
+        svg.push(format!(
+            "<rect x=\"{LABEL_WIDTH}\" y=\"{y:.1}\" width=\"{:.1}\" height=\"{BAR_HEIGHT}\" fill=\"#4e79a7\"><title>{value}</title></rect>",
+            value * scale
+        ));
+        svg.push(format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+            LABEL_WIDTH + value * scale + 4.0,
+            y + BAR_HEIGHT - 5.0,
+            value
+        ));
+    }
+    svg.push("</svg>".to_string());
+    Some(svg.join("\n"))
+}
+
+/// min to max whisker per run row, with percentile ticks and a dot at mean
+fn duration_chart(rows: &[Row]) -> Option<String> {
+    let rows = rows
+        .iter()
+        .filter(|row| {
+            number(row, "duration_ns_min").is_some() && number(row, "duration_ns_max").is_some()
+        })
+        .collect::<Vec<_>>();
+    let max = rows
+        .iter()
+        .filter_map(|row| number(row, "duration_ns_max"))
+        .fold(0.0, f64::max);
+    if rows.is_empty() || max <= 0.0 {
+        return None;
+    }
+
+    let scale = (CHART_WIDTH - LABEL_WIDTH - 20.0) / max;
+    let x = |v: f64| LABEL_WIDTH + v * scale;
+    let mut svg = svg_header(rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        let y = i as f64 * (BAR_HEIGHT + 4.0);
+        let mid = y + BAR_HEIGHT / 2.0;
+        let (min, max) = (
+            number(row, "duration_ns_min").unwrap(),
+            number(row, "duration_ns_max").unwrap(),
+        );
+        svg.push(format!(
+            "<text x=\"0\" y=\"{:.1}\">{}</text>",
+            y + BAR_HEIGHT - 5.0,
+            escape(&label(row))
+        ));
+        svg.push(format!(
+            "<line x1=\"{:.1}\" x2=\"{:.1}\" y1=\"{mid:.1}\" y2=\"{mid:.1}\" stroke=\"#888\"><title>min {min} max {max}</title></line>",
+            x(min),
+            x(max)
+        ));
+        for (k, v) in row.0.iter() {
+            if let (true, Some(v)) = (k.starts_with("duration_ns_p"), v.as_f64()) {
+                svg.push(format!(
+                    "<line x1=\"{0:.1}\" x2=\"{0:.1}\" y1=\"{1:.1}\" y2=\"{2:.1}\" stroke=\"#e15759\" stroke-width=\"2\"><title>{k} {v}</title></line>",
+                    x(v),
+                    y + 2.0,
+                    y + BAR_HEIGHT - 2.0
+                ));
+            }
+        }
+        if let Some(mean) = number(row, "duration_ns_mean") {
+            svg.push(format!(
+                "<circle cx=\"{:.1}\" cy=\"{mid:.1}\" r=\"4\" fill=\"#4e79a7\"><title>mean {mean}</title></circle>",
+                x(mean)
+            ));
+        }
+    }
+    svg.push("</svg>".to_string());
+    Some(svg.join("\n"))
+}
+
+fn svg_header(count: usize) -> Vec<String> {
+    vec![format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" height=\"{:.1}\">",
+        count as f64 * (BAR_HEIGHT + 4.0)
+    )]
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
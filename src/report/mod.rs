@@ -3,3 +3,32 @@ pub use run::*;
 
 mod build_crate;
 pub use build_crate::*;
+
+mod test;
+pub use test::*;
+
+mod self_profile;
+pub use self_profile::*;
+
+mod size;
+pub use size::*;
+
+mod repro;
+pub use repro::*;
+
+mod gate;
+pub use gate::*;
+
+mod compare;
+pub use compare::*;
+
+mod bisect;
+pub use bisect::*;
+
+mod patches;
+pub use patches::*;
+
+mod format;
+pub use format::*;
+
+mod html;
@@ -0,0 +1,91 @@
+use crate::bisect::PatchSeriesState;
+use crate::stats;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct PatchReportRow {
+    applied: usize,
+    /// patches the row's deltas are of, several if the prefixes before it are untestable
+    patch: String,
+    /// toolchain or crate failed to build or run with this prefix, no value is measured
+    untestable: bool,
+    binary_size: u64,
+    size_delta: i64,
+    size_delta_pct: f64,
+    samples: usize,
+    duration_ns_mean: Option<f64>,
+    duration_delta_pct: Option<f64>,
+    significant: Option<bool>,
+}
+
+/// one row per prefix of the patch series, from no patch to all of them. Deltas are the
+/// contribution of the row's patch, from the previous measured prefix, and percents are of
+/// the unpatched toolchain so they add up to the change of the whole series. Significance
+/// is Welch's t-test at 95% against the previous measured prefix
+pub fn report_patches(state: &PatchSeriesState) -> Vec<PatchReportRow> {
+    let round = |pct: f64| (pct * 100.0).round() / 100.0;
+    let prefixes = state.prefixes();
+    let base = prefixes.first().cloned();
+
+    let mut rows = vec![];
+    for (i, prefix) in prefixes.iter().enumerate() {
+        let previous = if i == 0 { None } else { Some(prefixes[i - 1]) };
+        let patch = match prefix.applied {
+            0 => "-".to_string(),
+            applied => {
+                let from = previous.map(|p| p.applied).unwrap_or_default();
+                state.stamp.patches[from..applied]
+                    .iter()
+                    .map(|(patch, _)| patch.as_str())
+                    .collect::<Vec<_>>()
+                    .join("+")
+            }
+        };
+        let size_delta = previous
+            .map(|p| prefix.binary_size as i64 - p.binary_size as i64)
+            .unwrap_or_default();
+        let base_size = base.map(|b| b.binary_size).unwrap_or_default().max(1);
+
+        let has_durations = !prefix.durations.is_empty();
+        let mean = stats::mean(&prefix.durations);
+        let previous_mean = previous.map(|p| stats::mean(&p.durations));
+        let base_mean = base.map(|b| stats::mean(&b.durations));
+        let duration_delta_pct = match (previous_mean, base_mean) {
+            (Some(previous_mean), Some(base_mean)) if base_mean > 0.0 => {
+                round((mean - previous_mean) / base_mean * 100.0)
+            }
+            _ => 0.0,
+        };
+        let significant = previous
+            .and_then(|p| stats::welch_t(&prefix.durations, &p.durations))
+            .map(|(t, df)| t.abs() > stats::t_critical_95(df))
+            .unwrap_or(false);
+
+        rows.push(PatchReportRow {
+            applied: prefix.applied,
+            patch,
+            untestable: false,
+            binary_size: prefix.binary_size,
+            size_delta,
+            size_delta_pct: round(size_delta as f64 / base_size as f64 * 100.0),
+            samples: prefix.durations.len(),
+            duration_ns_mean: has_durations.then_some(mean),
+            duration_delta_pct: has_durations.then_some(duration_delta_pct),
+            significant: has_durations.then_some(significant),
+        });
+    }
+    rows.extend(state.untestable.iter().map(|u| PatchReportRow {
+        applied: u.applied,
+        patch: state.stamp.patches[u.applied - 1].0.clone(),
+        untestable: true,
+        binary_size: 0,
+        size_delta: 0,
+        size_delta_pct: 0.0,
+        samples: 0,
+        duration_ns_mean: None,
+        duration_delta_pct: None,
+        significant: None,
+    }));
+    rows.sort_by_key(|r| r.applied);
+    rows
+}
@@ -0,0 +1,28 @@
+use crate::repro::ReproResult;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct ReproReportRow {
+    toolchain: String,
+    profile: String,
+    krate: String,
+    reproducible: bool,
+    hash: String,
+    other_hash: String,
+    first_diff_section: Option<String>,
+}
+
+pub fn report_repro_results(results: Vec<ReproResult>) -> Vec<ReproReportRow> {
+    results
+        .into_iter()
+        .map(|r| ReproReportRow {
+            toolchain: r.toolchain,
+            profile: r.profile,
+            krate: r.krate,
+            reproducible: r.hash == r.other_hash,
+            hash: r.hash,
+            other_hash: r.other_hash,
+            first_diff_section: r.first_diff_section,
+        })
+        .collect()
+}
@@ -1,9 +1,12 @@
 /// create report for different cmds
 /// Report here means print a list of flat json output, which can be easily displayed by nushell or
 /// dump into a sqlite db for further investigate
-use crate::run::RunResult;
+use crate::bench::BenchConditions;
+use crate::run::{RunResult, StopReason};
+use crate::stats;
 use itertools::Itertools;
 use serde::Serialize;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize)]
 pub struct RunReportRow {
@@ -12,22 +15,35 @@ pub struct RunReportRow {
     krate: String,
     cmd: String,
     binary_size: u64,
-    duration_ms_hist_min: u64,
-    duration_ms_hist_p50: u64,
-    duration_ms_hist_p90: u64,
-    duration_ms_hist_max: u64,
+    timeout_count: u64,
+    /// samples which didn't time out, the only ones durations are aggregated from
+    samples: u64,
+    stop_reason: Option<StopReason>,
+    outlier_count: u64,
+    /// duration statistics are `None` if every sample timed out
+    duration_ns_min: Option<u64>,
+    duration_ns_max: Option<u64>,
+    duration_ns_mean: Option<f64>,
+    duration_ns_stddev: Option<f64>,
+    duration_ns_mad: Option<f64>,
+    /// `duration_ns_p50` etc, one for each requested percentile
+    #[serde(flatten)]
+    duration_ns_percentiles: BTreeMap<String, f64>,
+    /// conditions when the first sample was taken
+    #[serde(flatten)]
+    conditions: Option<BenchConditions>,
 }
 
 /// convert `RunResult` to flat report rows
-/// aggregates all durations into histogram
-pub fn report_run_results(result: RunResult) -> Vec<RunReportRow> {
+/// aggregates all durations into statistics, with the requested `percentiles`
+pub fn report_run_results(result: RunResult, percentiles: &[f64]) -> Vec<RunReportRow> {
     let mut rows = vec![];
 
     for (_k, group) in &result
         .results
         .into_iter()
-        .sorted_by_key(|x| x.cmd.clone())
-        .group_by(|it| it.cmd.clone())
+        .sorted_by_key(|x| (x.krate.clone(), x.cmd.clone()))
+        .group_by(|it| (it.krate.clone(), it.cmd.clone()))
     {
         for (_k, group) in &group
             .into_iter()
@@ -36,18 +52,40 @@ pub fn report_run_results(result: RunResult) -> Vec<RunReportRow> {
             let mut krate: String = String::new();
             let mut toolchain: String = String::new();
             let mut profile: String = String::new();
-            let mut histogram = histogram::Histogram::new();
+            let mut durations = vec![];
             let mut cmd = String::new();
             let mut binary_size: u64 = 0;
+            let mut timeout_count: u64 = 0;
+            let mut stop_reason = None;
+            let mut conditions = None;
 
             for it in group.into_iter() {
-                histogram.increment(it.duration_ms).unwrap();
+                if it.timed_out {
+                    timeout_count += 1;
+                } else {
+                    durations.push(it.duration_ns as f64);
+                }
+                stop_reason = it.stop_reason.or(stop_reason);
+                conditions = conditions.or(Some(it.conditions));
                 krate = it.krate;
                 binary_size = it.binary_size;
                 toolchain = it.toolchain;
                 profile = it.profile;
                 cmd = it.cmd;
             }
+            durations.sort_by(f64::total_cmp);
+            let measured = !durations.is_empty();
+
+            let duration_ns_percentiles = percentiles
+                .iter()
+                .filter(|_| measured)
+                .map(|p| {
+                    (
+                        format!("duration_ns_p{p}"),
+                        stats::percentile(&durations, *p),
+                    )
+                })
+                .collect();
 
             rows.push(RunReportRow {
                 toolchain,
@@ -55,10 +93,21 @@ pub fn report_run_results(result: RunResult) -> Vec<RunReportRow> {
                 krate,
                 cmd,
                 binary_size,
-                duration_ms_hist_min: histogram.minimum().unwrap(),
-                duration_ms_hist_p50: histogram.percentile(50.0).unwrap(),
-                duration_ms_hist_p90: histogram.percentile(90.0).unwrap(),
-                duration_ms_hist_max: histogram.maximum().unwrap(),
+                timeout_count,
+                samples: durations.len() as u64,
+                stop_reason,
+                outlier_count: if measured {
+                    stats::iqr_outliers(&durations) as u64
+                } else {
+                    0
+                },
+                duration_ns_min: durations.first().map(|d| *d as u64),
+                duration_ns_max: durations.last().map(|d| *d as u64),
+                duration_ns_mean: measured.then(|| stats::mean(&durations)),
+                duration_ns_stddev: measured.then(|| stats::stddev(&durations)),
+                duration_ns_mad: measured.then(|| stats::median_absolute_deviation(&durations)),
+                duration_ns_percentiles,
+                conditions,
             })
         }
     }
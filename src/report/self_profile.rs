@@ -0,0 +1,67 @@
+use crate::self_profile::QueryProfile;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+pub struct SelfProfileReportRow {
+    toolchain: String,
+    profile: String,
+    krate: String,
+    query: String,
+    invocation_count: u64,
+    self_time_ms: f64,
+    baseline_self_time_ms: Option<f64>,
+    delta_ms: Option<f64>,
+    delta_pct: Option<f64>,
+}
+
+/// compare each query's self time with the same query of baseline toolchain and profile,
+/// rows are sorted by how much the self time changed
+pub fn report_self_profile(results: &[QueryProfile], baseline: &str) -> Vec<SelfProfileReportRow> {
+    let baseline_times = results
+        .iter()
+        .filter(|r| r.toolchain.eq(baseline))
+        .map(|r| {
+            (
+                (r.krate.as_str(), r.profile.as_str(), r.query.as_str()),
+                r.self_time_ns,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut rows = results
+        .iter()
+        .filter(|r| r.toolchain.ne(baseline))
+        .map(|r| {
+            let self_time_ms = r.self_time_ns as f64 / 1e6;
+            let baseline_self_time_ms = baseline_times
+                .get(&(r.krate.as_str(), r.profile.as_str(), r.query.as_str()))
+                .map(|ns| *ns as f64 / 1e6);
+            let delta_ms = baseline_self_time_ms.map(|b| self_time_ms - b);
+            let delta_pct = baseline_self_time_ms
+                .filter(|b| *b > 0.0)
+                .map(|b| (self_time_ms - b) / b * 100.0);
+
+            SelfProfileReportRow {
+                toolchain: r.toolchain.clone(),
+                profile: r.profile.clone(),
+                krate: r.krate.clone(),
+                query: r.query.clone(),
+                invocation_count: r.invocation_count,
+                self_time_ms,
+                baseline_self_time_ms,
+                delta_ms,
+                delta_pct,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    rows.sort_by(|a, b| {
+        let a_delta = a.delta_ms.unwrap_or(a.self_time_ms).abs();
+        let b_delta = b.delta_ms.unwrap_or(b.self_time_ms).abs();
+        (&a.toolchain, &a.profile)
+            .cmp(&(&b.toolchain, &b.profile))
+            .then(b_delta.total_cmp(&a_delta))
+    });
+    rows
+}
@@ -0,0 +1,135 @@
+use crate::size::SizeBreakdown;
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeKind {
+    Section,
+    Crate,
+    Symbol,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SizeReportRow {
+    toolchain: String,
+    profile: String,
+    krate: String,
+    kind: SizeKind,
+    name: String,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SizeDiffReportRow {
+    krate: String,
+    base: String,
+    target: String,
+    kind: SizeKind,
+    name: String,
+    base_size: u64,
+    size: u64,
+    delta: i64,
+}
+
+/// sizes grouped by kind and name, symbols with the same name are summed up
+fn size_entries(breakdown: &SizeBreakdown) -> BTreeMap<(SizeKind, String), u64> {
+    let mut entries = BTreeMap::new();
+
+    for section in breakdown.sections.iter() {
+        *entries
+            .entry((SizeKind::Section, section.name.clone()))
+            .or_default() += section.size;
+    }
+    for symbol in breakdown.symbols.iter() {
+        *entries
+            .entry((SizeKind::Symbol, symbol.name.clone()))
+            .or_default() += symbol.size;
+        *entries
+            .entry((SizeKind::Crate, crate::binary::symbol_crate(&symbol.name)))
+            .or_default() += symbol.size;
+    }
+
+    entries
+}
+
+/// flat rows of sections, crates and top `top` symbols for each artifact
+pub fn report_size_breakdown(breakdowns: &[SizeBreakdown], top: usize) -> Vec<SizeReportRow> {
+    let mut rows = vec![];
+
+    for breakdown in breakdowns.iter() {
+        for (kind, group) in &size_entries(breakdown)
+            .into_iter()
+            .group_by(|((kind, _), _)| *kind)
+        {
+            let group = group.sorted_by(|a, b| b.1.cmp(&a.1));
+            let limit = match kind {
+                SizeKind::Symbol => top,
+                SizeKind::Section | SizeKind::Crate => usize::MAX,
+            };
+
+            for ((kind, name), size) in group.take(limit) {
+                rows.push(SizeReportRow {
+                    toolchain: breakdown.toolchain.clone(),
+                    profile: breakdown.profile.clone(),
+                    krate: breakdown.krate.clone(),
+                    kind,
+                    name,
+                    size,
+                });
+            }
+        }
+    }
+
+    rows
+}
+
+/// diff two artifacts, rows sorted by how much the size changed. Only top `top`
+/// changed symbols are kept
+pub fn report_size_diff(
+    base: &SizeBreakdown,
+    target: &SizeBreakdown,
+    top: usize,
+) -> Vec<SizeDiffReportRow> {
+    let base_entries = size_entries(base);
+    let target_entries = size_entries(target);
+
+    let keys = base_entries
+        .keys()
+        .chain(target_entries.keys())
+        .cloned()
+        .collect::<std::collections::BTreeSet<_>>();
+
+    let mut rows = vec![];
+    for (kind, group) in &keys.into_iter().group_by(|(kind, _)| *kind) {
+        let limit = match kind {
+            SizeKind::Symbol => top,
+            SizeKind::Section | SizeKind::Crate => usize::MAX,
+        };
+
+        let group = group
+            .map(|key| {
+                let base_size = base_entries.get(&key).cloned().unwrap_or_default();
+                let size = target_entries.get(&key).cloned().unwrap_or_default();
+                (key.1, base_size, size, size as i64 - base_size as i64)
+            })
+            .filter(|(_, _, _, delta)| *delta != 0 || kind != SizeKind::Symbol)
+            .sorted_by(|a, b| b.3.abs().cmp(&a.3.abs()));
+
+        for (name, base_size, size, delta) in group.take(limit) {
+            rows.push(SizeDiffReportRow {
+                krate: target.krate.clone(),
+                base: format!("{}:{}", base.toolchain, base.profile),
+                target: format!("{}:{}", target.toolchain, target.profile),
+                kind,
+                name,
+                base_size,
+                size,
+                delta,
+            });
+        }
+    }
+
+    rows
+}
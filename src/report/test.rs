@@ -0,0 +1,112 @@
+use crate::test_crate::{TestOutcome, TestResult};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+pub struct TestReportRow {
+    toolchain: String,
+    profile: String,
+    krate: String,
+    binary: String,
+    test: String,
+    outcome: Option<TestOutcome>,
+    baseline_outcome: Option<TestOutcome>,
+}
+
+/// convert `TestResult`s to flat report rows, each row is compared to the test with same
+/// profile and test binary in baseline toolchain. If `all` is false, only rows differ from
+/// baseline are kept
+pub fn report_test_results(
+    results: &[TestResult],
+    baseline: &str,
+    all: bool,
+) -> Vec<TestReportRow> {
+    let baseline_outcomes = results
+        .iter()
+        .filter(|r| r.toolchain.eq(baseline))
+        .map(|r| {
+            (
+                (r.profile.as_str(), r.binary.as_str(), r.test.as_str()),
+                r.outcome,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut rows = vec![];
+
+    for r in results.iter().filter(|r| r.toolchain.ne(baseline)) {
+        let baseline_outcome = baseline_outcomes
+            .get(&(r.profile.as_str(), r.binary.as_str(), r.test.as_str()))
+            .cloned();
+        if all || baseline_outcome != Some(r.outcome) {
+            rows.push(TestReportRow {
+                toolchain: r.toolchain.clone(),
+                profile: r.profile.clone(),
+                krate: r.krate.clone(),
+                binary: r.binary.clone(),
+                test: r.test.clone(),
+                outcome: Some(r.outcome),
+                baseline_outcome,
+            });
+        }
+    }
+
+    // tests only exist in baseline, e.g. the test binary failed to build for other toolchain
+    for ((profile, binary, test), outcome) in baseline_outcomes.iter() {
+        // baseline failing to test has no tests to miss
+        if *outcome == TestOutcome::Error {
+            continue;
+        }
+        for (toolchain, krate) in results
+            .iter()
+            .filter(|r| r.toolchain.ne(baseline) && r.profile.eq(profile))
+            .map(|r| (r.toolchain.as_str(), r.krate.as_str()))
+            .collect::<std::collections::BTreeSet<_>>()
+        {
+            let exists = results.iter().any(|r| {
+                r.toolchain.eq(toolchain)
+                    && r.profile.eq(profile)
+                    && r.binary.eq(binary)
+                    && r.test.eq(test)
+            });
+            if !exists {
+                rows.push(TestReportRow {
+                    toolchain: toolchain.to_string(),
+                    profile: profile.to_string(),
+                    krate: krate.to_string(),
+                    binary: binary.to_string(),
+                    test: test.to_string(),
+                    outcome: None,
+                    baseline_outcome: Some(*outcome),
+                });
+            }
+        }
+    }
+
+    if all {
+        rows.extend(
+            results
+                .iter()
+                .filter(|r| r.toolchain.eq(baseline))
+                .map(|r| TestReportRow {
+                    toolchain: r.toolchain.clone(),
+                    profile: r.profile.clone(),
+                    krate: r.krate.clone(),
+                    binary: r.binary.clone(),
+                    test: r.test.clone(),
+                    outcome: Some(r.outcome),
+                    baseline_outcome: Some(r.outcome),
+                }),
+        );
+    }
+
+    rows.sort_by(|a, b| {
+        (&a.toolchain, &a.profile, &a.binary, &a.test).cmp(&(
+            &b.toolchain,
+            &b.profile,
+            &b.binary,
+            &b.test,
+        ))
+    });
+    rows
+}
@@ -0,0 +1,212 @@
+/// check whether builds are bit-for-bit reproducible, by building each (toolchain, profile)
+/// twice in separate target folders and comparing the artifacts
+use crate::config::{Config, CrateOpt, Profile, ToolchainConfig};
+use crate::plan::{quote, PlanStep};
+use crate::utils;
+
+#[derive(Debug)]
+pub struct ReproResult {
+    /// crate name
+    pub krate: String,
+
+    /// toolchain name
+    pub toolchain: String,
+
+    /// profile name
+    pub profile: String,
+
+    /// sha256 of artifact from the first build
+    pub hash: String,
+
+    /// sha256 of artifact from the second build
+    pub other_hash: String,
+
+    /// first section differs, only set when hashes differ
+    pub first_diff_section: Option<String>,
+}
+
+/// check reproducibility of crate for each toolchain and profile. If `remap` is true, the
+/// second build happens in a copy of the crate at another path, and both builds remap
+/// their paths with `--remap-path-prefix`
+pub fn repro_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+    remap: bool,
+) -> anyhow::Result<Vec<ReproResult>> {
+    let mut results = vec![];
+
+    for toolchain in config.toolchains.iter() {
+        for profile in toolchain.profiles.iter() {
+            let profile = config.profile(profile)?;
+            results.push(repro_step(krate, toolchain, profile, config, remap)?);
+        }
+    }
+
+    Ok(results)
+}
+
+pub fn repro_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+    remap: bool,
+) -> anyhow::Result<ReproResult> {
+    utils::checkout_crate(krate, config)?;
+    if remap {
+        copy_step(krate, config).execute()?;
+    }
+
+    let mut artifacts = vec![];
+    for (step, target_folder) in build_steps(krate, toolchain, profile, config, remap) {
+        step.execute()?;
+        artifacts.push(target_folder.join(&krate.output_path));
+    }
+
+    let (artifact, other_artifact) = (&artifacts[0], &artifacts[1]);
+    let hash = crate::metadata::sha256(artifact)?;
+    let other_hash = crate::metadata::sha256(other_artifact)?;
+    let first_diff_section = if hash != other_hash {
+        Some(
+            crate::binary::first_diff_section(artifact, other_artifact)?
+                .unwrap_or_else(|| "[headers]".to_string()),
+        )
+    } else {
+        None
+    };
+
+    Ok(ReproResult {
+        krate: krate.name.clone(),
+        toolchain: toolchain.name.clone(),
+        profile: profile.name.clone(),
+        hash,
+        other_hash,
+        first_diff_section,
+    })
+}
+
+/// the step copying crate checkout for the second build with `remap`, without the
+/// (possibly huge) target folder. Copied anew every time so it follows the checkout
+fn copy_step(krate: &CrateOpt, config: &Config) -> PlanStep {
+    let other_arg = quote(remap_folder(krate, config).to_str().unwrap());
+    let mut step = PlanStep::new(format!("copy {} for remapped build", krate.name));
+    step.cwd = Some(utils::crate_folder(krate, config));
+    step.cmds.push(format!("mkdir -p {other_arg}"));
+    step.cmds.push(format!(
+        "find {other_arg} -mindepth 1 -maxdepth 1 ! -name target -exec rm -rf {{}} +"
+    ));
+    step.cmds.push(format!(
+        "tar --format=posix --exclude=./target -cf - . | tar -xf - -C {other_arg}"
+    ));
+    step
+}
+
+/// the steps of both builds of a cell, each with the fresh target folder it builds into
+fn build_steps(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+    remap: bool,
+) -> Vec<(PlanStep, std::path::PathBuf)> {
+    let folder_path = utils::crate_folder(krate, config);
+    let other_folder_path = if remap {
+        remap_folder(krate, config)
+    } else {
+        folder_path.clone()
+    };
+
+    let mut steps = vec![];
+    for (variant, folder) in [("repro_a", &folder_path), ("repro_b", &other_folder_path)] {
+        let target_folder =
+            utils::target_folder_variant(krate, profile, toolchain, config, variant);
+        let mut step = PlanStep::new(format!(
+            "repro {} {}_{} {}",
+            krate.name, toolchain.name, profile.name, variant
+        ));
+        step.cwd = Some(folder.clone());
+        step.env = repro_environ(
+            krate,
+            toolchain,
+            profile,
+            config,
+            folder,
+            &target_folder,
+            remap,
+        )
+        .into_iter()
+        .collect();
+        step.cmds
+            .push(format!("rm -rf {}", quote(target_folder.to_str().unwrap())));
+        step.cmds.push(krate.release_build_cmd());
+        step.notes
+            .push("sha256 of the artifact is compared with the other build".to_string());
+        steps.push((step, target_folder));
+    }
+    steps
+}
+
+/// copy of crate checkout the second build happens in with `remap`
+fn remap_folder(krate: &CrateOpt, config: &Config) -> std::path::PathBuf {
+    config
+        .global
+        .build_root()
+        .join(format!("{}_repro", krate.name))
+}
+
+/// build environ building in `folder` into `target_folder`, both remapped if `remap`
+fn repro_environ(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+    folder: &std::path::Path,
+    target_folder: &std::path::Path,
+    remap: bool,
+) -> std::collections::HashMap<String, String> {
+    let mut environs = utils::build_environ(krate, profile, toolchain, config);
+    environs.insert(
+        "CARGO_TARGET_DIR".to_string(),
+        target_folder.to_str().unwrap().to_string(),
+    );
+    if remap {
+        let rustflags = format!(
+            "{} --remap-path-prefix={}=/ctm/src --remap-path-prefix={}=/ctm/target",
+            environs.get("RUSTFLAGS").cloned().unwrap_or_default(),
+            folder.to_str().unwrap(),
+            target_folder.to_str().unwrap(),
+        );
+        environs.insert("RUSTFLAGS".to_string(), rustflags.trim().to_string());
+    }
+    environs
+}
+
+/// plan of `repro_for_all_profile`
+pub fn plan_repro_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+    remap: bool,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let folder_path = utils::crate_folder(krate, config);
+    let mut steps = vec![utils::plan_checkout(krate, config)?];
+    if remap {
+        let mut step = copy_step(krate, config);
+        step.disk_bytes = folder_path
+            .exists()
+            .then(|| utils::dir_size(&folder_path))
+            .transpose()?;
+        steps.push(step);
+    }
+
+    for toolchain in config.toolchains.iter() {
+        for profile in toolchain.profiles.iter() {
+            let profile = config.profile(profile)?;
+            for (mut step, target_folder) in build_steps(krate, toolchain, profile, config, remap) {
+                step.disk_bytes = crate::plan::estimate_new_folder(&target_folder)?;
+                steps.push(step);
+            }
+        }
+    }
+
+    Ok(steps)
+}
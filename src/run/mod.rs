@@ -1,11 +1,17 @@
-use crate::config::{Config, CrateOpt, Profile, ToolchainConfig};
+use crate::bench::BenchConditions;
+use crate::config::{Config, CrateOpt, Profile, Run, ToolchainConfig};
+use crate::plan::{quote, PlanStep};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::os::unix::process::CommandExt;
 
 #[derive(Debug)]
 pub struct RunResult {
     pub results: Vec<OneRunResult>,
 }
 
-#[derive(Debug)]
+/// one sample of a run, exported as is with `--samples`
+#[derive(Debug, Serialize, Deserialize)]
 pub struct OneRunResult {
     /// crate name
     pub krate: String,
@@ -19,38 +25,85 @@ pub struct OneRunResult {
     /// cmd name
     pub cmd: String,
 
+    /// index of sample in run, starts from 0
+    pub sample_index: u64,
+
+    /// unix timestamp in milliseconds when sample started
+    pub timestamp_ms: u64,
+
     /// how large the binary is, useful for report
     pub binary_size: u64,
 
     /// how long it taks for one cmd run
-    pub duration_ms: u64,
+    pub duration_ns: u64,
+
+    /// whether the cmd was killed because of timeout
+    pub timed_out: bool,
+
+    /// exit code, `None` if killed by signal
+    pub exit_code: Option<i32>,
+
+    /// why sampling stopped, only set on the last sample of a run
+    pub stop_reason: Option<StopReason>,
+
+    /// resource usage of cmd
+    #[serde(flatten)]
+    pub rusage: ResourceUsage,
+
+    /// machine conditions when sampling
+    #[serde(flatten)]
+    pub conditions: BenchConditions,
+}
+
+/// resource usage of a sample's process, from `wait4`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub user_time_ns: u64,
+    pub sys_time_ns: u64,
+    pub max_rss_kb: u64,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    pub voluntary_ctx_switches: u64,
+    pub involuntary_ctx_switches: u64,
 }
 
-/// run command with each toolchain and krate by inject PATH
+/// why no more samples are taken for a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// fixed `count` reached
+    Count,
+    /// adaptive: confidence interval is narrow enough
+    Stable,
+    /// adaptive: `max_count` reached
+    MaxCount,
+    /// adaptive: `max_time_ms` reached
+    MaxTime,
+}
+
+/// run commands of krate with each toolchain and profile picked by filter, artifact folder
+/// is injected into PATH. Missing or stale artifacts are rebuilt if `rebuild`, otherwise
+/// it is an error
 pub fn run_cmds(
-    profile_to_run: Option<&str>,
+    filter: &crate::utils::CellFilter,
     config: &crate::config::Config,
     krate: &crate::config::CrateOpt,
+    rebuild: bool,
 ) -> anyhow::Result<RunResult> {
     let mut run_results = vec![];
 
     for toolchain in &config.toolchains {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
         for profile in toolchain.profiles.iter() {
-            let should_run = profile_to_run
-                .map(|p| p.eq(profile.as_str()))
-                .unwrap_or(true);
-            if !should_run {
+            if !filter.profile(profile) {
                 continue;
             }
 
-            let profile = config
-                .profiles
-                .iter()
-                .filter(|p| p.name.eq(profile))
-                .nth(0)
-                .unwrap();
+            let profile = config.profile(profile)?;
 
-            let mut results = run_cmd_step(krate, toolchain, profile, config)?;
+            let mut results = run_cmd_step(krate, toolchain, profile, config, filter, rebuild)?;
             run_results.append(&mut results);
         }
     }
@@ -60,47 +113,627 @@ pub fn run_cmds(
     })
 }
 
-fn run_cmd_step(
+/// plan of `run_cmds`
+pub fn plan_run_cmds(
+    filter: &crate::utils::CellFilter,
+    config: &Config,
+    krate: &CrateOpt,
+    rebuild: bool,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let mut steps = vec![];
+    let mut checkout_planned = false;
+
+    for toolchain in &config.toolchains {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
+        for profile in toolchain.profiles.iter() {
+            if !filter.profile(profile) {
+                continue;
+            }
+            let profile = config.profile(profile)?;
+
+            let mut stale = None;
+            if !crate::build_crate::is_up_to_date(krate, toolchain, profile, config)? {
+                if rebuild {
+                    if !checkout_planned {
+                        steps.push(crate::utils::plan_checkout(krate, config)?);
+                        checkout_planned = true;
+                    }
+                    steps.push(crate::build_crate::plan_build_crate_step(
+                        krate,
+                        toolchain,
+                        profile,
+                        crate::config::BuildMode::Release,
+                        config,
+                        false,
+                    )?);
+                } else {
+                    stale = Some(
+                        "fails: artifact is missing or stale, build it with `ctm build-crate` or run with --rebuild",
+                    );
+                }
+            }
+
+            let mut run_steps = plan_run_samples(krate, toolchain, profile, config, filter)?;
+            for step in run_steps.iter_mut() {
+                step.notes.extend(stale.map(str::to_string));
+            }
+            steps.append(&mut run_steps);
+        }
+    }
+
+    Ok(steps)
+}
+
+/// plan of the runs of one cell, with artifact assumed to be up to date
+pub fn plan_run_samples(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+    filter: &crate::utils::CellFilter,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let mut steps = vec![];
+    let program = crate::utils::artifact_path(krate, profile, toolchain, config);
+    for run in krate.runs.iter().filter(|r| filter.run(&r.name)) {
+        let context = RunContext::new(krate, run, &program, config)?;
+        let mut step = PlanStep::new(format!(
+            "run {} {}_{} {}",
+            krate.name, toolchain.name, profile.name, run.name
+        ));
+        step.cwd = context.cwd.clone();
+        step.env = context.environs.clone().into_iter().collect();
+        step.disk_bytes = Some(0);
+
+        match run.adaptive.as_ref() {
+            Some(adaptive) => step.notes.push(format!(
+                "prepare and sample {} to {} times, until relative ci is below {}{}",
+                adaptive.min_count,
+                adaptive.max_count,
+                adaptive.target_rel_ci,
+                adaptive
+                    .max_time_ms
+                    .map(|ms| format!(" or {ms}ms in total"))
+                    .unwrap_or_default()
+            )),
+            None => step
+                .notes
+                .push(format!("prepare and sample {} times", run.count)),
+        }
+        if let Some(timeout_ms) = run.timeout_ms {
+            step.notes
+                .push(format!("each sample is killed after {timeout_ms}ms"));
+        }
+        step.notes.extend(crate::bench::describe(&config.bench));
+
+        step.cmds.extend(run.setup.iter().cloned());
+        step.cmds.extend(run.prepare.iter().cloned());
+        step.cmds.push(context.sample_cmd_line());
+        step.cmds.extend(run.teardown.iter().cloned());
+        steps.push(step);
+    }
+
+    Ok(steps)
+}
+
+pub fn run_cmd_step(
     krate: &CrateOpt,
     toolchain: &ToolchainConfig,
     profile: &Profile,
     config: &Config,
+    filter: &crate::utils::CellFilter,
+    rebuild: bool,
 ) -> anyhow::Result<Vec<OneRunResult>> {
-    let target_folder = crate::utils::target_folder(krate, profile, toolchain, config)
-        .into_os_string()
-        .to_str()
-        .unwrap()
-        .to_owned();
-    let program = std::path::PathBuf::from(&target_folder).join(&krate.output_path);
+    if !crate::build_crate::is_up_to_date(krate, toolchain, profile, config)? {
+        if !rebuild {
+            anyhow::bail!(
+                "artifact of {} with {}_{} is missing, stale or built by an older ctm without fingerprint, build it with `ctm build-crate` or run with --rebuild",
+                krate.name,
+                toolchain.name,
+                profile.name
+            );
+        }
+        crate::build_crate::build_crate_step(
+            krate,
+            toolchain,
+            profile,
+            crate::config::BuildMode::Release,
+            config,
+            false,
+        )?;
+    }
+
+    let program = crate::utils::artifact_path(krate, profile, toolchain, config);
     log::info!("running program: {:?}", program);
 
+    let mut run_results = vec![];
+
+    for run in krate.runs.iter().filter(|r| filter.run(&r.name)) {
+        let context = RunContext::new(krate, run, &program, config)?;
+
+        run_hooks(&run.setup, run, &context)?;
+        let results = run_samples(krate, toolchain, profile, run, &program, &context, config);
+        // teardown even if samples failed, to leave a clean state
+        run_hooks(&run.teardown, run, &context)?;
+
+        run_results.append(&mut results?);
+    }
+    Ok(run_results)
+}
+
+/// where, with what env and what a run executes, for samples and hooks
+struct RunContext {
+    cwd: Option<std::path::PathBuf>,
+
+    /// run's env, PATH has artifact folder injected
+    environs: std::collections::HashMap<String, String>,
+
+    /// program and args of a sample, `bash -c <shell>` for shell runs
+    argv: Vec<String>,
+
+    /// file samples read from stdin, `/dev/null` if `None`
+    stdin: Option<std::path::PathBuf>,
+}
+
+impl RunContext {
+    fn new(
+        krate: &CrateOpt,
+        run: &Run,
+        program: &std::path::Path,
+        config: &Config,
+    ) -> anyhow::Result<Self> {
+        let cwd = run
+            .cwd
+            .as_ref()
+            .map(|cwd| crate::utils::resolve_run_path(cwd, krate, config));
+        let mut environs = run.env.clone();
+        let path = environs
+            .get("PATH")
+            .map(std::ffi::OsString::from)
+            .or_else(|| std::env::var_os("PATH"))
+            .unwrap_or_default();
+        // stripped artifact first, then the rest of cargo's output, e.g: other binaries
+        let stripped_folder = program.parent().unwrap();
+        let output_path = stripped_folder.parent().unwrap().join(&krate.output_path);
+        let mut paths = vec![
+            stripped_folder.to_path_buf(),
+            output_path.parent().unwrap().to_path_buf(),
+        ];
+        paths.extend(std::env::split_paths(&path));
+        let path = std::env::join_paths(paths)?
+            .into_string()
+            .map_err(|path| anyhow::anyhow!("PATH {path:?} isn't valid unicode"))?;
+        environs.insert("PATH".to_string(), path);
+
+        let argv = match run.shell.as_ref() {
+            Some(shell) => vec!["bash".to_string(), "-c".to_string(), shell.clone()],
+            None => std::iter::once(program.to_str().unwrap().to_string())
+                .chain(run.args.iter().cloned())
+                .collect(),
+        };
+        let stdin = run
+            .stdin
+            .as_ref()
+            .map(|stdin| crate::utils::resolve_run_path(stdin, krate, config));
+        Ok(RunContext {
+            cwd,
+            environs,
+            argv,
+            stdin,
+        })
+    }
+
+    fn command(&self, program: impl AsRef<std::ffi::OsStr>) -> std::process::Command {
+        let mut cmd = std::process::Command::new(program);
+        cmd.envs(&self.environs);
+        if let Some(cwd) = self.cwd.as_ref() {
+            cmd.current_dir(cwd);
+        }
+        cmd
+    }
+
+    /// command of one sample, with stdin and output redirected
+    fn sample_command(&self) -> anyhow::Result<std::process::Command> {
+        let mut cmd = self.command(&self.argv[0]);
+        cmd.args(&self.argv[1..])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+        match self.stdin.as_ref() {
+            Some(stdin) => cmd.stdin(std::fs::File::open(stdin)?),
+            None => cmd.stdin(std::process::Stdio::null()),
+        };
+        Ok(cmd)
+    }
+
+    /// `sample_command` as a shell cmd line, for `--dry-run`
+    fn sample_cmd_line(&self) -> String {
+        let stdin = match self.stdin.as_ref() {
+            Some(stdin) => quote(stdin.to_str().unwrap()),
+            None => "/dev/null".to_string(),
+        };
+        format!(
+            "{} < {stdin} > /dev/null 2>&1",
+            self.argv.iter().map(|arg| quote(arg)).join(" ")
+        )
+    }
+}
+
+/// run program (or `run.shell`) `run.count` times, `run.prepare` hooks are executed
+/// before each sample and not counted into duration
+fn run_samples(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    run: &Run,
+    program: &std::path::Path,
+    context: &RunContext,
+    config: &Config,
+) -> anyhow::Result<Vec<OneRunResult>> {
+    let timeout = run.timeout_ms.map(std::time::Duration::from_millis);
+
     let file_size = {
-        let meta = std::fs::metadata(&program)?;
+        let meta = std::fs::metadata(program)?;
         meta.len()
     };
 
-    let mut run_results = vec![];
+    if run.adaptive.is_none() && run.count == 0 {
+        anyhow::bail!("run {} has neither count nor adaptive", run.name);
+    }
+
+    let conditions = crate::bench::check_conditions(&config.bench)?;
+
+    let mut run_results: Vec<OneRunResult> = vec![];
+    let mut durations = vec![];
+    let mut total_time = std::time::Duration::ZERO;
 
-    for run in krate.runs.iter() {
-        for _i in 0..run.count {
-            let start = std::time::Instant::now();
-            let output = std::process::Command::new(program.clone())
-                .args(run.args.as_slice())
-                .output()?;
+    loop {
+        let samples = run_results.len() as u64;
+        if let Some(reason) = stop_reason(run, samples, &durations, total_time) {
+            if let Some(last) = run_results.last_mut() {
+                last.stop_reason = Some(reason);
+            }
+            log::info!(
+                "{} stopped after {} samples: {:?}",
+                run.name,
+                samples,
+                reason
+            );
+            break;
+        }
 
-            let duration_ms = std::time::Instant::now().duration_since(start).as_millis() as u64;
+        run_hooks(&run.prepare, run, context)?;
 
-            run_results.push(OneRunResult {
-                krate: krate.name.clone(),
-                toolchain: toolchain.name.clone(),
-                profile: profile.name.clone(),
-                cmd: run.name.clone(),
-                binary_size: file_size,
-                duration_ms,
-            });
+        let mut cmd = context.sample_command()?;
+        if timeout.is_some() {
+            // own process group, so the whole pipeline can be killed on timeout. Ctrl-C
+            // from terminal doesn't reach it any more, ctm forwards it
+            cmd.process_group(0);
+            forward_interrupt();
+        }
+        crate::bench::apply(&mut cmd, &config.bench)?;
 
-            log::info!("program done with status {:?}", output.status);
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis() as u64;
+        let start = std::time::Instant::now();
+        let child = cmd.spawn()?;
+        let (exit, timed_out) = wait_with_timeout(&child, timeout)?;
+        let duration = std::time::Instant::now().duration_since(start);
+        let duration_ns = duration.as_nanos() as u64;
+        total_time += duration;
+        // a killed sample says nothing about how long the program takes
+        if !timed_out {
+            durations.push(duration_ns as f64);
+        }
+
+        run_results.push(OneRunResult {
+            krate: krate.name.clone(),
+            toolchain: toolchain.name.clone(),
+            profile: profile.name.clone(),
+            cmd: run.name.clone(),
+            sample_index: run_results.len() as u64,
+            timestamp_ms,
+            binary_size: file_size,
+            duration_ns,
+            timed_out,
+            exit_code: exit.exit_code,
+            stop_reason: None,
+            rusage: exit.rusage,
+            conditions: conditions.clone(),
+        });
+
+        if timed_out {
+            log::warn!("program timed out after {:?}", timeout.unwrap());
+        } else {
+            log::info!("program done with exit code {:?}", exit.exit_code);
         }
     }
+
     Ok(run_results)
 }
+
+/// decide whether to stop sampling after `n` samples, `None` means keep going.
+/// `durations` are of the samples which didn't time out
+fn stop_reason(
+    run: &Run,
+    n: u64,
+    durations: &[f64],
+    total_time: std::time::Duration,
+) -> Option<StopReason> {
+    let adaptive = match run.adaptive.as_ref() {
+        Some(adaptive) => adaptive,
+        None if n >= run.count => return Some(StopReason::Count),
+        None => return None,
+    };
+
+    if n >= adaptive.max_count {
+        return Some(StopReason::MaxCount);
+    }
+    if n > 0 {
+        if let Some(max_time_ms) = adaptive.max_time_ms {
+            if total_time.as_millis() as u64 >= max_time_ms {
+                return Some(StopReason::MaxTime);
+            }
+        }
+    }
+    if n >= adaptive.min_count
+        && durations.len() >= 2
+        && relative_ci(durations) <= adaptive.target_rel_ci
+    {
+        return Some(StopReason::Stable);
+    }
+    None
+}
+
+/// half width of the 95% confidence interval of mean, relative to mean
+fn relative_ci(samples: &[f64]) -> f64 {
+    let n = samples.len();
+    let mean = crate::stats::mean(samples);
+    let t = crate::stats::t_critical_95(n - 1);
+    let half_width = t * crate::stats::stddev(samples) / (n as f64).sqrt();
+
+    if mean > 0.0 {
+        half_width / mean
+    } else {
+        0.0
+    }
+}
+
+/// execute hook shell cmds one by one, with the same env and cwd as the run
+fn run_hooks(hooks: &[String], run: &Run, context: &RunContext) -> anyhow::Result<()> {
+    for hook in hooks.iter() {
+        log::debug!("running hook for {}: {}", run.name, hook);
+
+        let status = context
+            .command("bash")
+            .args(["-c", hook])
+            .stdout(std::process::Stdio::inherit())
+            .stderr(std::process::Stdio::inherit())
+            .status()?;
+        if !status.success() {
+            anyhow::bail!("hook `{hook}` of {} failed with {status:?}", run.name);
+        }
+    }
+    Ok(())
+}
+
+/// exit code and resource usage of a reaped child
+struct ChildExit {
+    exit_code: Option<i32>,
+    rusage: ResourceUsage,
+}
+
+/// wait for child to exit, kill it if it runs longer than `timeout`.
+/// returns how it exited and whether it was killed because of timeout
+fn wait_with_timeout(
+    child: &std::process::Child,
+    timeout: Option<std::time::Duration>,
+) -> anyhow::Result<(ChildExit, bool)> {
+    let pid = child.id() as libc::pid_t;
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok((wait4(pid)?, false)),
+    };
+
+    SAMPLE_GROUP.store(pid, std::sync::atomic::Ordering::SeqCst);
+    // the watcher kills child on timeout, waiting in current thread keeps the duration
+    // measurement accurate
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    let watcher = std::thread::spawn(move || match done_rx.recv_timeout(timeout) {
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+            // child leads its own process group, kill the group
+            unsafe { libc::kill(-pid, libc::SIGKILL) };
+            true
+        }
+        _ => false,
+    });
+
+    // child is only reaped once the watcher is done, before that its pid (and process
+    // group) can't be reused, so the watcher never kills an unrelated process
+    wait_exited(pid)?;
+    SAMPLE_GROUP.store(0, std::sync::atomic::Ordering::SeqCst);
+    let _ = done_tx.send(());
+    let timed_out = watcher.join().unwrap();
+    Ok((wait4(pid)?, timed_out))
+}
+
+/// process group of the sample being waited for, 0 if none
+static SAMPLE_GROUP: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+/// pass SIGINT on to the process group of the sample, then let it terminate ctm as usual
+fn forward_interrupt() {
+    extern "C" fn handler(signal: libc::c_int) {
+        let group = SAMPLE_GROUP.load(std::sync::atomic::Ordering::SeqCst);
+        unsafe {
+            if group > 0 {
+                libc::killpg(group, signal);
+            }
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| unsafe {
+        let handler = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // keep ignoring it if ctm runs with SIGINT ignored, e.g: in background
+        if libc::signal(libc::SIGINT, handler) == libc::SIG_IGN {
+            libc::signal(libc::SIGINT, libc::SIG_IGN);
+        }
+    });
+}
+
+/// wait until child exits without reaping it
+fn wait_exited(pid: libc::pid_t) -> anyhow::Result<()> {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let options = libc::WEXITED | libc::WNOWAIT;
+    loop {
+        if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, options) } >= 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+}
+
+/// reap child with `wait4`, which also reports its resource usage
+fn wait4(pid: libc::pid_t) -> anyhow::Result<ChildExit> {
+    let mut status = 0;
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        if unsafe { libc::wait4(pid, &mut status, 0, &mut rusage) } >= 0 {
+            break;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+
+    let time_ns = |t: libc::timeval| t.tv_sec as u64 * 1_000_000_000 + t.tv_usec as u64 * 1_000;
+    Ok(ChildExit {
+        exit_code: libc::WIFEXITED(status).then(|| libc::WEXITSTATUS(status)),
+        rusage: ResourceUsage {
+            user_time_ns: time_ns(rusage.ru_utime),
+            sys_time_ns: time_ns(rusage.ru_stime),
+            max_rss_kb: rusage.ru_maxrss as u64,
+            minor_faults: rusage.ru_minflt as u64,
+            major_faults: rusage.ru_majflt as u64,
+            voluntary_ctx_switches: rusage.ru_nvcsw as u64,
+            involuntary_ctx_switches: rusage.ru_nivcsw as u64,
+        },
+    })
+}
+
+/// columns of a samples csv which are strings, the others are parsed as numbers
+const STRING_COLUMNS: [&str; 8] = [
+    "krate",
+    "toolchain",
+    "profile",
+    "cmd",
+    "stop_reason",
+    "cpu_governor",
+    "cpu_affinity",
+    "ionice",
+];
+
+/// load samples exported with `--samples`, from csv if path ends with `.csv`, jsonl
+/// otherwise
+pub fn load_samples(path: &std::path::Path) -> anyhow::Result<RunResult> {
+    let content = std::fs::read_to_string(path)?;
+
+    let results = if path.extension().map(|e| e == "csv").unwrap_or(false) {
+        crate::report::parse_csv(&content)?
+            .into_iter()
+            .map(|record| {
+                let object = record
+                    .into_iter()
+                    .map(|(column, cell)| {
+                        let value = if cell.is_empty() {
+                            serde_json::Value::Null
+                        } else if STRING_COLUMNS.contains(&column.as_str()) {
+                            serde_json::Value::String(cell)
+                        } else {
+                            serde_json::from_str(&cell)
+                                .map_err(|e| anyhow::anyhow!("bad {column} {cell:?}: {e}"))?
+                        };
+                        Ok((column, value))
+                    })
+                    .collect::<anyhow::Result<serde_json::Map<_, _>>>()?;
+                Ok(serde_json::from_value(serde_json::Value::Object(object))?)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    } else {
+        content
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !crate::report::is_metadata_line(l))
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    Ok(RunResult { results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn run(toml: &str) -> Run {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn relative_ci_of_samples() {
+        assert_eq!(relative_ci(&[10.0, 10.0, 10.0]), 0.0);
+        // stddev 1, t of 2 degrees of freedom 4.303
+        let expected = 4.303 / 3f64.sqrt() / 10.0;
+        assert!((relative_ci(&[9.0, 10.0, 11.0]) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stop_at_count() {
+        let run = run("name = 'r'\ncount = 3");
+        assert_eq!(stop_reason(&run, 2, &[1.0, 1.0], Duration::ZERO), None);
+        assert_eq!(
+            stop_reason(&run, 3, &[1.0, 1.0, 1.0], Duration::ZERO),
+            Some(StopReason::Count)
+        );
+        // timed out samples have no duration but still count
+        assert_eq!(
+            stop_reason(&run, 3, &[], Duration::ZERO),
+            Some(StopReason::Count)
+        );
+    }
+
+    #[test]
+    fn stop_adaptive() {
+        let run = run("name = 'r'
+[adaptive]
+min_count = 3
+max_count = 6
+target_rel_ci = 0.01
+max_time_ms = 1000");
+        let stable = [100.0, 100.1, 99.9];
+        let noisy = [50.0, 100.0, 150.0];
+        assert_eq!(stop_reason(&run, 2, &stable[..2], Duration::ZERO), None);
+        assert_eq!(
+            stop_reason(&run, 3, &stable, Duration::ZERO),
+            Some(StopReason::Stable)
+        );
+        assert_eq!(stop_reason(&run, 3, &noisy, Duration::ZERO), None);
+        assert_eq!(
+            stop_reason(&run, 6, &noisy, Duration::ZERO),
+            Some(StopReason::MaxCount)
+        );
+        assert_eq!(
+            stop_reason(&run, 3, &noisy, Duration::from_secs(1)),
+            Some(StopReason::MaxTime)
+        );
+        // stable durations of the samples which didn't time out aren't enough
+        assert_eq!(stop_reason(&run, 4, &stable[..1], Duration::ZERO), None);
+    }
+}
@@ -0,0 +1,231 @@
+/// build crate with rustc `-Zself-profile` and collect per query timing with measureme's
+/// `summarize` tool, which can be installed with
+/// `cargo install --git https://github.com/rust-lang/measureme summarize`
+use crate::config::{Config, CrateOpt, Profile, ToolchainConfig};
+use crate::plan::{quote, PlanStep};
+use crate::utils;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug)]
+pub struct QueryProfile {
+    /// crate name
+    pub krate: String,
+
+    /// toolchain name
+    pub toolchain: String,
+
+    /// profile name
+    pub profile: String,
+
+    /// query or pass name, e.g: `typeck`, `LLVM_passes`
+    pub query: String,
+
+    /// time spent in query itself, excluding nested queries
+    pub self_time_ns: u64,
+
+    /// how many times query invoked
+    pub invocation_count: u64,
+}
+
+#[derive(Deserialize)]
+struct SummarizeDuration {
+    secs: u64,
+    nanos: u64,
+}
+
+#[derive(Deserialize)]
+struct SummarizeQueryData {
+    label: String,
+    self_time: SummarizeDuration,
+    invocation_count: u64,
+}
+
+/// json emitted by `summarize summarize --json`
+#[derive(Deserialize)]
+struct SummarizeResults {
+    query_data: Vec<SummarizeQueryData>,
+}
+
+/// self profile crate for each toolchain and profile
+pub fn self_profile_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+    filter: &utils::CellFilter,
+) -> anyhow::Result<Vec<QueryProfile>> {
+    let mut results = vec![];
+
+    for toolchain in config.toolchains.iter() {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
+        for profile in toolchain.profiles.iter() {
+            if !filter.profile(profile) {
+                continue;
+            }
+            let profile = config.profile(profile)?;
+            let mut step_results = self_profile_step(krate, toolchain, profile, config)?;
+            results.append(&mut step_results);
+        }
+    }
+
+    Ok(results)
+}
+
+/// crates whose self profile data is summarized
+fn profiled_crates(krate: &CrateOpt) -> Vec<String> {
+    if krate.self_profile_crates.is_empty() {
+        vec![krate.name.replace('-', "_")]
+    } else {
+        krate.self_profile_crates.clone()
+    }
+}
+
+/// build environ with a dedicated target folder and `-Zself-profile` writing into it
+fn self_profile_environ(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> HashMap<String, String> {
+    let target_folder =
+        utils::target_folder_variant(krate, profile, toolchain, config, "self_profile");
+    let profile_folder = target_folder.join("self-profile");
+
+    let mut environs = utils::build_environ(krate, profile, toolchain, config);
+    environs.insert(
+        "CARGO_TARGET_DIR".to_string(),
+        target_folder.to_str().unwrap().to_string(),
+    );
+    let rustflags = format!(
+        "{} -Zself-profile={}",
+        environs.get("RUSTFLAGS").cloned().unwrap_or_default(),
+        profile_folder.to_str().unwrap()
+    );
+    environs.insert("RUSTFLAGS".to_string(), rustflags.trim().to_string());
+    environs
+}
+
+pub fn self_profile_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> anyhow::Result<Vec<QueryProfile>> {
+    utils::checkout_crate(krate, config)?;
+    profile_step(krate, toolchain, profile, config).execute()?;
+
+    let profile_folder =
+        utils::target_folder_variant(krate, profile, toolchain, config, "self_profile")
+            .join("self-profile");
+    // profile files are named as `{crate_name}-{pid}.mm_profdata`
+    let crate_names = profiled_crates(krate);
+
+    let mut queries = BTreeMap::<String, (u64, u64)>::new();
+    for entry in std::fs::read_dir(&profile_folder)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+        let selected = file_name.ends_with(".mm_profdata")
+            && crate_names
+                .iter()
+                .any(|n| file_name.starts_with(&format!("{n}-")));
+        if !selected {
+            continue;
+        }
+
+        // summarized by the step into `{crate_name}-{pid}.json`
+        let content = std::fs::read_to_string(path.with_extension("json"))?;
+        let summary: SummarizeResults = serde_json::from_str(&content)?;
+
+        for q in summary.query_data.into_iter() {
+            let entry = queries.entry(q.label).or_default();
+            entry.0 += q.self_time.secs * 1_000_000_000 + q.self_time.nanos;
+            entry.1 += q.invocation_count;
+        }
+    }
+
+    if queries.is_empty() {
+        anyhow::bail!("no self profile data found for {crate_names:?} in {profile_folder:?}");
+    }
+
+    Ok(queries
+        .into_iter()
+        .map(|(query, (self_time_ns, invocation_count))| QueryProfile {
+            krate: krate.name.clone(),
+            toolchain: toolchain.name.clone(),
+            profile: profile.name.clone(),
+            query,
+            self_time_ns,
+            invocation_count,
+        })
+        .collect())
+}
+
+/// the step building crate with self profiling into a dedicated target folder, so profiled
+/// build never pollutes the measured artifacts, and everything is rebuilt to get the
+/// profile data. Profile data of profiled crates is then summarized into json
+fn profile_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> PlanStep {
+    let target_folder =
+        utils::target_folder_variant(krate, profile, toolchain, config, "self_profile");
+    let profile_folder = target_folder.join("self-profile");
+    let (target_folder_arg, profile_folder_arg) = (
+        quote(target_folder.to_str().unwrap()),
+        quote(profile_folder.to_str().unwrap()),
+    );
+
+    let mut step = PlanStep::new(format!(
+        "self-profile {} {}_{}",
+        krate.name, toolchain.name, profile.name
+    ));
+    step.cwd = Some(utils::crate_folder(krate, config));
+    step.env = self_profile_environ(krate, toolchain, profile, config)
+        .into_iter()
+        .collect();
+    step.cmds.push(format!("rm -rf {target_folder_arg}"));
+    step.cmds.push(format!("mkdir -p {profile_folder_arg}"));
+    step.cmds.push(krate.release_build_cmd());
+    for crate_name in profiled_crates(krate) {
+        step.cmds.push(format!(
+            "for f in {profile_folder_arg}/{}-*.mm_profdata; do [ -e \"$f\" ] || continue; summarize summarize --json \"$f\" || exit 1; done",
+            quote(&crate_name)
+        ));
+    }
+    step
+}
+
+/// plan of `self_profile_for_all_profile`
+pub fn plan_self_profile_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+    filter: &utils::CellFilter,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let mut steps = vec![];
+
+    for toolchain in config.toolchains.iter() {
+        if !filter.toolchain(&toolchain.name) {
+            continue;
+        }
+        for profile in toolchain.profiles.iter() {
+            if !filter.profile(profile) {
+                continue;
+            }
+            let profile = config.profile(profile)?;
+            let mut step = profile_step(krate, toolchain, profile, config);
+            step.disk_bytes = crate::plan::estimate_new_folder(&utils::target_folder_variant(
+                krate,
+                profile,
+                toolchain,
+                config,
+                "self_profile",
+            ))?;
+            steps.push(step);
+        }
+    }
+
+    Ok(steps)
+}
@@ -0,0 +1,68 @@
+use crate::binary::{self, Section, Symbol};
+use crate::config::{Config, CrateOpt, Profile, ToolchainConfig};
+
+#[derive(Debug)]
+pub struct SizeBreakdown {
+    /// crate name
+    pub krate: String,
+
+    /// toolchain name
+    pub toolchain: String,
+
+    /// profile name
+    pub profile: String,
+
+    /// sections of the (stripped) artifact
+    pub sections: Vec<Section>,
+
+    /// symbols of the unstripped copy, empty if there is no unstripped copy
+    pub symbols: Vec<Symbol>,
+}
+
+/// collect size breakdown of crate artifacts for each toolchain and profile,
+/// crate should be built before this
+pub fn size_breakdown_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+) -> anyhow::Result<Vec<SizeBreakdown>> {
+    let mut breakdowns = vec![];
+
+    for toolchain in config.toolchains.iter() {
+        for profile in toolchain.profiles.iter() {
+            let profile = config.profile(profile)?;
+            breakdowns.push(size_breakdown_step(krate, toolchain, profile, config)?);
+        }
+    }
+
+    Ok(breakdowns)
+}
+
+pub fn size_breakdown_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> anyhow::Result<SizeBreakdown> {
+    let artifact = crate::utils::artifact_path(krate, profile, toolchain, config);
+    if !artifact.exists() {
+        anyhow::bail!("artifact {artifact:?} not found, run build-crate first");
+    }
+
+    let sections = binary::sections(&artifact)?;
+
+    let unstripped = crate::utils::cargo_output_path(krate, profile, toolchain, config);
+    let symbols = if unstripped.exists() {
+        binary::symbols(&unstripped)?
+    } else {
+        log::warn!("no unstripped artifact {unstripped:?}, skip symbols");
+        vec![]
+    };
+
+    Ok(SizeBreakdown {
+        krate: krate.name.clone(),
+        toolchain: toolchain.name.clone(),
+        profile: profile.name.clone(),
+        sections,
+        symbols,
+    })
+}
@@ -0,0 +1,128 @@
+//! descriptive statistics over duration samples
+
+/// arithmetic mean, 0 for empty samples
+pub fn mean(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.iter().sum::<f64>() / samples.len() as f64
+}
+
+/// sample standard deviation, 0 if less than 2 samples
+pub fn stddev(samples: &[f64]) -> f64 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(samples);
+    let variance =
+        samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (samples.len() - 1) as f64;
+    variance.sqrt()
+}
+
+/// percentile with linear interpolation between closest ranks, `sorted` must be sorted
+/// ascending and not empty, `p` is in [0, 100]
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// median of absolute deviations from the median, `sorted` must be sorted ascending
+/// and not empty
+pub fn median_absolute_deviation(sorted: &[f64]) -> f64 {
+    let median = percentile(sorted, 50.0);
+    let mut deviations = sorted
+        .iter()
+        .map(|x| (x - median).abs())
+        .collect::<Vec<_>>();
+    deviations.sort_by(f64::total_cmp);
+    percentile(&deviations, 50.0)
+}
+
+/// count samples outside of [q1 - 1.5 * iqr, q3 + 1.5 * iqr], `sorted` must be sorted
+/// ascending and not empty
+pub fn iqr_outliers(sorted: &[f64]) -> usize {
+    let q1 = percentile(sorted, 25.0);
+    let q3 = percentile(sorted, 75.0);
+    let iqr = q3 - q1;
+    let (low, high) = (q1 - 1.5 * iqr, q3 + 1.5 * iqr);
+    sorted.iter().filter(|x| **x < low || **x > high).count()
+}
+
+/// geometric mean of positive samples, 0 for empty samples
+pub fn geomean(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|x| x.ln()).sum::<f64>() / samples.len() as f64).exp()
+}
+
+/// two sided 95% critical value of t-distribution, normal approximation above 30 degrees
+/// of freedom
+pub fn t_critical_95(df: usize) -> f64 {
+    const T_95: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    T_95.get(df.max(1) - 1).cloned().unwrap_or(1.96)
+}
+
+/// Welch's t statistic of mean(a) - mean(b) and its degrees of freedom, `None` if either
+/// has less than 2 samples or both have no variance
+pub fn welch_t(a: &[f64], b: &[f64]) -> Option<(f64, usize)> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let (va, vb) = (stddev(a).powi(2) / na, stddev(b).powi(2) / nb);
+    if va + vb == 0.0 {
+        return None;
+    }
+    let t = (mean(a) - mean(b)) / (va + vb).sqrt();
+    let df = (va + vb).powi(2) / (va.powi(2) / (na - 1.0) + vb.powi(2) / (nb - 1.0));
+    Some((t, df.floor() as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_and_stddev() {
+        assert_eq!(mean(&[]), 0.0);
+        assert_eq!(mean(&[1.0, 2.0, 6.0]), 3.0);
+        assert_eq!(stddev(&[5.0]), 0.0);
+        // sample variance (4 + 0 + 4) / 2
+        assert_eq!(stddev(&[1.0, 3.0, 5.0]), 2.0);
+    }
+
+    #[test]
+    fn percentile_interpolates() {
+        let sorted = [10.0, 20.0, 30.0, 40.0];
+        assert_eq!(percentile(&sorted, 0.0), 10.0);
+        assert_eq!(percentile(&sorted, 50.0), 25.0);
+        assert_eq!(percentile(&sorted, 90.0), 37.0);
+        assert_eq!(percentile(&sorted, 100.0), 40.0);
+        assert_eq!(percentile(&sorted, 150.0), 40.0);
+        assert_eq!(percentile(&[7.0], 99.0), 7.0);
+    }
+
+    #[test]
+    fn spread() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 100.0];
+        // deviations from median 3 are 0, 1, 1, 2, 97
+        assert_eq!(median_absolute_deviation(&sorted), 1.0);
+        assert_eq!(iqr_outliers(&sorted), 1);
+        assert_eq!(iqr_outliers(&[1.0, 2.0, 3.0, 4.0]), 0);
+    }
+
+    #[test]
+    fn t_critical() {
+        assert_eq!(t_critical_95(0), 12.706);
+        assert_eq!(t_critical_95(1), 12.706);
+        assert_eq!(t_critical_95(30), 2.042);
+        assert_eq!(t_critical_95(31), 1.96);
+    }
+}
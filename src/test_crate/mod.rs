@@ -0,0 +1,319 @@
+use crate::config::{Config, CrateOpt, Profile, ToolchainConfig};
+use crate::plan::PlanStep;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+
+/// outcome of one test case, as reported by libtest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOutcome {
+    Ok,
+    Failed,
+    Ignored,
+
+    /// test cmd failed without running any test, e.g. the crate failed to compile or
+    /// rustc crashed
+    Error,
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    /// crate name
+    pub krate: String,
+
+    /// toolchain name
+    pub toolchain: String,
+
+    /// profile name
+    pub profile: String,
+
+    /// test binary, e.g: `unittests src/lib.rs`, `tests/cli.rs` or `doc-tests dust`.
+    /// Empty for the `Error` outcome
+    pub binary: String,
+
+    /// test name, e.g: `tests::it_works`
+    pub test: String,
+
+    /// test outcome
+    pub outcome: TestOutcome,
+}
+
+/// run test suite of crate for each toolchain and profile
+pub fn test_crate_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+) -> anyhow::Result<Vec<TestResult>> {
+    let mut results = vec![];
+
+    for toolchain in config.toolchains.iter() {
+        for profile in toolchain.profiles.iter() {
+            let profile = config.profile(profile)?;
+            let mut step_results = test_crate_step(krate, toolchain, profile, config)?;
+            results.append(&mut step_results);
+        }
+    }
+
+    Ok(results)
+}
+
+fn test_cmd(krate: &CrateOpt) -> String {
+    krate
+        .test_cmd
+        .as_ref()
+        .cloned()
+        .unwrap_or_else(|| "cargo test --release --no-fail-fast".to_string())
+}
+
+/// the step running the test cmd of a cell, with stderr merged into stdout as cargo prints
+/// the test binary names there
+fn test_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> PlanStep {
+    let mut step = PlanStep::new(format!(
+        "test {} {}_{}",
+        krate.name, toolchain.name, profile.name
+    ));
+    step.cwd = Some(utils::crate_folder(krate, config));
+    step.env = utils::build_environ(krate, profile, toolchain, config)
+        .into_iter()
+        .collect();
+    step.cmds.push(format!("exec 2>&1\n{}", test_cmd(krate)));
+    step.notes
+        .push("test outcomes are parsed from libtest output".to_string());
+    step
+}
+
+/// plan of `test_crate_for_all_profile`
+pub fn plan_test_crate_for_all_profile(
+    krate: &CrateOpt,
+    config: &Config,
+) -> anyhow::Result<Vec<PlanStep>> {
+    let mut steps = vec![utils::plan_checkout(krate, config)?];
+
+    for toolchain in config.toolchains.iter() {
+        for profile in toolchain.profiles.iter() {
+            let profile = config.profile(profile)?;
+            let mut step = test_step(krate, toolchain, profile, config);
+            step.disk_bytes = crate::plan::estimate_new_folder(&utils::target_folder(
+                krate, profile, toolchain, config,
+            ))?;
+            steps.push(step);
+        }
+    }
+
+    Ok(steps)
+}
+
+pub fn test_crate_step(
+    krate: &CrateOpt,
+    toolchain: &ToolchainConfig,
+    profile: &Profile,
+    config: &Config,
+) -> anyhow::Result<Vec<TestResult>> {
+    utils::checkout_crate(krate, config)?;
+    let step = test_step(krate, toolchain, profile, config);
+    log::info!("{}: {}", step.title, test_cmd(krate));
+
+    // test failures make the cmd exit non zero, so the status is checked after parsing
+    let output = step
+        .command(&step.cmds[0])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::inherit())
+        .output()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut outcomes = parse_libtest_output(&stdout);
+    if outcomes.is_empty() && !output.status.success() {
+        let lines = stdout.lines().collect::<Vec<_>>();
+        log::warn!(
+            "{} failed with {}, last output:\n{}",
+            step.title,
+            output.status,
+            lines[lines.len().saturating_sub(20)..].join("\n")
+        );
+        outcomes.push((String::new(), String::new(), TestOutcome::Error));
+    }
+
+    Ok(outcomes
+        .into_iter()
+        .map(|(binary, test, outcome)| TestResult {
+            krate: krate.name.clone(),
+            toolchain: toolchain.name.clone(),
+            profile: profile.name.clone(),
+            binary,
+            test,
+            outcome,
+        })
+        .collect())
+}
+
+/// test binary which a `Running` or `Doc-tests` line of cargo starts, without the hash
+/// in its file name which differs between toolchains
+fn parse_test_binary(line: &str) -> Option<String> {
+    if let Some(krate) = line.strip_prefix("Doc-tests ") {
+        return Some(format!("doc-tests {krate}"));
+    }
+    let target = line.strip_prefix("Running ")?;
+    // `unittests src/lib.rs (target/release/deps/dust-1a2b3c)`, older cargo only
+    // prints the path
+    match target.rsplit_once(" (") {
+        Some((source, _)) => Some(source.to_string()),
+        None => {
+            let file = std::path::Path::new(target).file_name()?.to_str()?;
+            Some(
+                file.rsplit_once('-')
+                    .map(|(name, _)| name)
+                    .unwrap_or(file)
+                    .to_string(),
+            )
+        }
+    }
+}
+
+/// one event line from libtest's json output (`-Z unstable-options --format json`)
+#[derive(Deserialize)]
+struct JsonTestEvent {
+    #[serde(rename = "type")]
+    ty: String,
+    event: String,
+    #[serde(default)]
+    name: String,
+}
+
+/// parse (binary, test, outcome) from cargo test output, both the human readable
+/// `test foo ... ok` lines and json event lines are supported
+fn parse_libtest_output(output: &str) -> Vec<(String, String, TestOutcome)> {
+    let mut outcomes = vec![];
+    let mut binary = String::new();
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(name) = parse_test_binary(line) {
+            binary = name;
+            continue;
+        }
+
+        if line.starts_with('{') {
+            let event = match serde_json::from_str::<JsonTestEvent>(line) {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+            if event.ty != "test" {
+                continue;
+            }
+            let outcome = match event.event.as_str() {
+                "ok" => TestOutcome::Ok,
+                "failed" | "timeout" => TestOutcome::Failed,
+                "ignored" => TestOutcome::Ignored,
+                _ => continue,
+            };
+            outcomes.push((binary.clone(), event.name, outcome));
+            continue;
+        }
+
+        let line = match line.strip_prefix("test ") {
+            Some(line) => line,
+            None => continue,
+        };
+        let (name, result) = match line.rsplit_once(" ... ") {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let outcome = if result == "ok" {
+            TestOutcome::Ok
+        } else if result == "FAILED" {
+            TestOutcome::Failed
+        } else if result.starts_with("ignored") {
+            TestOutcome::Ignored
+        } else {
+            continue;
+        };
+        outcomes.push((binary.clone(), name.to_string(), outcome));
+    }
+
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_without_hash() {
+        assert_eq!(
+            parse_test_binary("Running unittests src/lib.rs (target/release/deps/dust-1a2b3c)"),
+            Some("unittests src/lib.rs".to_string())
+        );
+        assert_eq!(
+            parse_test_binary("Running target/release/deps/cli-4d5e6f"),
+            Some("cli".to_string())
+        );
+        assert_eq!(
+            parse_test_binary("Doc-tests dust"),
+            Some("doc-tests dust".to_string())
+        );
+        assert_eq!(parse_test_binary("test foo ... ok"), None);
+    }
+
+    #[test]
+    fn libtest_human_output() {
+        let output = "
+     Running unittests src/main.rs (target/release/deps/dust-1a2b3c)
+
+running 3 tests
+test tests::ok ... ok
+test tests::ign ... ignored, slow
+test tests::fails ... FAILED
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out
+
+   Doc-tests dust
+
+running 1 test
+test src/lib.rs - walk (line 3) ... ok
+";
+        let binary = "unittests src/main.rs".to_string();
+        assert_eq!(
+            parse_libtest_output(output),
+            vec![
+                (binary.clone(), "tests::ok".to_string(), TestOutcome::Ok),
+                (
+                    binary.clone(),
+                    "tests::ign".to_string(),
+                    TestOutcome::Ignored
+                ),
+                (binary, "tests::fails".to_string(), TestOutcome::Failed),
+                (
+                    "doc-tests dust".to_string(),
+                    "src/lib.rs - walk (line 3)".to_string(),
+                    TestOutcome::Ok
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn libtest_json_output() {
+        let output = r#"
+     Running tests/cli.rs (target/release/deps/cli-4d5e6f)
+{ "type": "suite", "event": "started", "test_count": 3 }
+{ "type": "test", "event": "started", "name": "a" }
+{ "type": "test", "name": "a", "event": "ok" }
+{ "type": "test", "name": "b", "event": "timeout" }
+{ "type": "test", "name": "c", "event": "ignored" }
+{ "type": "suite", "event": "ok", "passed": 1, "failed": 0 }
+"#;
+        let binary = "tests/cli.rs".to_string();
+        assert_eq!(
+            parse_libtest_output(output),
+            vec![
+                (binary.clone(), "a".to_string(), TestOutcome::Ok),
+                (binary.clone(), "b".to_string(), TestOutcome::Failed),
+                (binary, "c".to_string(), TestOutcome::Ignored),
+            ]
+        );
+    }
+}
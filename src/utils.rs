@@ -7,6 +7,310 @@ pub fn target_folder(
     toolchain: &ToolchainConfig,
     config: &Config,
 ) -> std::path::PathBuf {
-    let crate_folder = config.global.build_root().join(&k.name);
+    let crate_folder = crate_folder(k, config);
     crate_folder.join(format!("target/{}_{}", toolchain.name, profile.name))
 }
+
+/// cargo's output artifact in target folder, which is never modified so it is the
+/// unstripped artifact
+pub fn cargo_output_path(
+    k: &CrateOpt,
+    profile: &Profile,
+    toolchain: &ToolchainConfig,
+    config: &Config,
+) -> std::path::PathBuf {
+    target_folder(k, profile, toolchain, config).join(&k.output_path)
+}
+
+/// the measured artifact, a stripped copy of cargo's output in `ctm-stripped` of target
+/// folder with the same file name, e.g: `target/base_minsize/ctm-stripped/dust`
+pub fn artifact_path(
+    k: &CrateOpt,
+    profile: &Profile,
+    toolchain: &ToolchainConfig,
+    config: &Config,
+) -> std::path::PathBuf {
+    let output_path = cargo_output_path(k, profile, toolchain, config);
+    target_folder(k, profile, toolchain, config)
+        .join("ctm-stripped")
+        .join(output_path.file_name().unwrap())
+}
+
+/// get a target folder next to `target_folder`, for builds should not touch the measured
+/// artifacts, e.g: `target/base_minsize_self_profile`
+pub fn target_folder_variant(
+    k: &CrateOpt,
+    profile: &Profile,
+    toolchain: &ToolchainConfig,
+    config: &Config,
+    variant: &str,
+) -> std::path::PathBuf {
+    let crate_folder = crate_folder(k, config);
+    crate_folder.join(format!(
+        "target/{}_{}_{}",
+        toolchain.name, profile.name, variant
+    ))
+}
+
+/// get the folder crate is checked out or copied to
+pub fn crate_folder(k: &CrateOpt, config: &Config) -> std::path::PathBuf {
+    config.global.build_root().join(&k.name)
+}
+
+/// resolve path used by crate runs, `{crate}` is replaced with crate checkout folder,
+/// relative path is relative to project root
+pub fn resolve_run_path(path: &str, k: &CrateOpt, config: &Config) -> std::path::PathBuf {
+    let path = path.replace("{crate}", crate_folder(k, config).to_str().unwrap());
+    config.global.to_absolute_path(&path)
+}
+
+/// checkout crate into build root, or sync the sources of a local crate into an existing
+/// checkout, target folders are kept
+pub fn checkout_crate(k: &CrateOpt, config: &Config) -> anyhow::Result<std::path::PathBuf> {
+    let step = plan_checkout(k, config)?;
+    if step.cached.is_none() {
+        step.execute()?;
+    }
+    Ok(crate_folder(k, config))
+}
+
+/// plan of `checkout_crate`
+pub fn plan_checkout(k: &CrateOpt, config: &Config) -> anyhow::Result<crate::plan::PlanStep> {
+    use crate::plan::{quote, PlanStep};
+
+    let build_root = config.global.build_root();
+    let folder_path = crate_folder(k, config);
+    let folder_arg = quote(folder_path.to_str().unwrap());
+    let mut step = PlanStep::new(format!("checkout {}", k.name));
+
+    if folder_path.exists() {
+        match crate_source_path(k, config) {
+            Some(source) => {
+                // copied anew so files removed from source go away too, tar keeps mtimes
+                // so cargo only rebuilds what changed. posix format keeps them to the
+                // nanosecond, an edit in the same second as the last build is still newer
+                step.title = format!("sync source of {}", k.name);
+                step.cwd = Some(source);
+                step.cmds.push(format!(
+                    "find {folder_arg} -mindepth 1 -maxdepth 1 ! -name target -exec rm -rf {{}} +"
+                ));
+                step.cmds.push(format!(
+                    "tar --format=posix --exclude=./target -cf - . | tar -xf - -C {folder_arg}"
+                ));
+                step.disk_bytes = Some(0);
+            }
+            None => step.cached = Some("already checked out".to_string()),
+        }
+        return Ok(step);
+    }
+
+    // build root may not exist yet, so no cwd
+    step.cmds
+        .push(format!("mkdir -p {}", quote(build_root.to_str().unwrap())));
+    if let Some(git) = k.git.as_ref() {
+        step.cmds
+            .push(format!("git clone {} {folder_arg}", quote(git)));
+    } else if let Some(source) = crate_source_path(k, config) {
+        step.cmds.push(format!(
+            "cp -R {} {folder_arg}",
+            quote(source.to_str().unwrap())
+        ));
+        step.disk_bytes = source.exists().then(|| dir_size(&source)).transpose()?;
+    } else {
+        anyhow::bail!("Neither git nor path provided");
+    }
+    Ok(step)
+}
+
+/// local source path of crate, relative path is relative to build root as in
+/// `checkout_crate`. `None` for git crates
+pub fn crate_source_path(k: &CrateOpt, config: &Config) -> Option<std::path::PathBuf> {
+    k.path
+        .as_ref()
+        .map(|path| config.global.build_root().join(path))
+}
+
+/// environment variables for cargo to build crate with (toolchain, profile)
+pub fn build_environ(
+    k: &CrateOpt,
+    profile: &Profile,
+    toolchain: &ToolchainConfig,
+    config: &Config,
+) -> std::collections::HashMap<String, String> {
+    let target_folder = target_folder(k, profile, toolchain, config)
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let mut environs = profile.environ.clone();
+    environs.insert("CARGO_TARGET_DIR".to_string(), target_folder);
+    environs.insert("RUSTUP_TOOLCHAIN".to_string(), toolchain.name.clone());
+    environs
+}
+
+/// glob filters picking the (crate, toolchain, profile, run) cells to build or run,
+/// empty filter matches everything
+#[derive(Debug, Default)]
+pub struct CellFilter {
+    pub crates: Vec<String>,
+    pub toolchains: Vec<String>,
+    pub profiles: Vec<String>,
+    pub runs: Vec<String>,
+}
+
+impl CellFilter {
+    pub fn krate(&self, krate: &CrateOpt) -> bool {
+        matches_any(&self.crates, &krate.name)
+            && (self.runs.is_empty() || krate.runs.iter().any(|r| self.run(&r.name)))
+    }
+
+    pub fn toolchain(&self, name: &str) -> bool {
+        matches_any(&self.toolchains, name)
+    }
+
+    pub fn profile(&self, name: &str) -> bool {
+        matches_any(&self.profiles, name)
+    }
+
+    pub fn run(&self, name: &str) -> bool {
+        matches_any(&self.runs, name)
+    }
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.is_empty() || patterns.iter().any(|p| glob_match(p, name))
+}
+
+/// match `text` against glob `pattern`, `*` matches any chars and `?` matches one char
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (
+        pattern.chars().collect::<Vec<_>>(),
+        text.chars().collect::<Vec<_>>(),
+    );
+    let (mut p, mut t) = (0, 0);
+    // position of last `*` in pattern, and of text it is matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // let the last `*` eat one more char
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// latest modification time of files under folder, `skip` are top level entries not
+/// looked into, e.g: `target`
+pub fn newest_mtime(
+    folder: &std::path::Path,
+    skip: &[&str],
+) -> anyhow::Result<Option<std::time::SystemTime>> {
+    let root = folder;
+    let mut newest = None;
+    let mut folders = vec![root.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        for entry in std::fs::read_dir(&folder)? {
+            let entry = entry?;
+            if folder == root && skip.iter().any(|s| entry.file_name() == **s) {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                folders.push(entry.path());
+            } else {
+                newest = newest.max(Some(entry.metadata()?.modified()?));
+            }
+        }
+    }
+    Ok(newest)
+}
+
+/// total size of files under folder, symlinks are not followed
+pub fn dir_size(folder: &std::path::Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    let mut folders = vec![folder.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        for entry in std::fs::read_dir(&folder)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                folders.push(entry.path());
+            } else if file_type.is_file() {
+                size += entry.metadata()?.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(crates: &[&str], runs: &[&str]) -> CellFilter {
+        CellFilter {
+            crates: crates.iter().map(|s| s.to_string()).collect(),
+            runs: runs.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("stable", "stable"));
+        assert!(!glob_match("stable", "stable2"));
+        assert!(glob_match("patched*", "patched"));
+        assert!(glob_match("patched*", "patched-opt"));
+        assert!(!glob_match("patched*", "unpatched"));
+        assert!(glob_match("*-opt", "patched-opt"));
+        assert!(glob_match("p?t*d", "patched"));
+        assert!(!glob_match("p?t", "pt"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(!glob_match("*a*b", "xxbxxa"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn empty_filter_matches_all() {
+        let filter = CellFilter::default();
+        assert!(filter.toolchain("stable"));
+        assert!(filter.profile("release"));
+        assert!(filter.run("bench"));
+    }
+
+    #[test]
+    fn filter_by_crate_and_run() {
+        let krate: CrateOpt = toml::from_str(
+            r#"
+            name = "fib"
+            output_path = "release/fib"
+            [[runs]]
+            name = "small"
+            [[runs]]
+            name = "large"
+            "#,
+        )
+        .unwrap();
+
+        assert!(filter(&["f*"], &[]).krate(&krate));
+        assert!(!filter(&["other", "fi"], &[]).krate(&krate));
+        // a crate without any matching run has nothing to run
+        assert!(filter(&[], &["lar*"]).krate(&krate));
+        assert!(!filter(&[], &["medium"]).krate(&krate));
+
+        let filter = filter(&[], &["s*", "large"]);
+        assert!(filter.run("small") && filter.run("large"));
+        assert!(!filter.run("medium"));
+    }
+}